@@ -0,0 +1,270 @@
+use crate::error::MarketError;
+use crate::price::Price;
+use crate::structs::{FeeSchedule, Order, OrderKind, Transaction};
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A user's currency and items. What their resting orders could still trade is held in
+/// escrow and is not available to spend, sell or withdraw.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub balance: Price,
+    #[serde(default)]
+    pub items: HashMap<String, u32>,
+    /// Currency held for resting buy orders. Rebuilt from the book on load.
+    #[serde(default)]
+    pub held: Price,
+    /// Items held for resting sell orders. Rebuilt from the book on load.
+    #[serde(default)]
+    pub held_items: HashMap<String, u32>,
+}
+
+impl Account {
+    pub fn available(&self) -> Price {
+        self.balance.checked_sub(self.held).unwrap_or_default()
+    }
+
+    pub fn available_items(&self, item: &str) -> u32 {
+        let owned = self.items.get(item).copied().unwrap_or(0);
+        owned.saturating_sub(self.held_items.get(item).copied().unwrap_or(0))
+    }
+}
+
+/// What an order needs held to cover everything it could still trade
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hold {
+    user: String,
+    item: String,
+    currency: Price,
+    items: u32,
+}
+
+impl Hold {
+    /// A limit buy holds what it would pay at its own price, plus the most it could owe in
+    /// fees: the higher of the maker and taker rates, and a minimum fee for every unit, as
+    /// each could be a trade of its own. A sell holds the items, as its fees come out of what
+    /// it is paid. Market buys hold nothing, as they are sized by the available balance
+    /// instead.
+    pub fn for_order(item: &str, order: &Order, fees: Option<&FeeSchedule>) -> Hold {
+        let amount = order.amount + order.reserve;
+        let (currency, items) = match order.kind {
+            OrderKind::BUY | OrderKind::STOP_LIMIT_BUY => {
+                let cost = order.price_per.checked_mul(amount).unwrap_or_default();
+                let fee = fees.map(|x| x.most(cost, amount)).unwrap_or_default();
+                (cost.checked_add(fee).unwrap_or(cost), 0)
+            }
+            OrderKind::MARKET_BUY | OrderKind::STOP_BUY => (Price::default(), 0),
+            _ => (Price::default(), amount),
+        };
+
+        Hold { user: order.user_id.clone(), item: item.to_string(), currency, items }
+    }
+
+    /// Enough for either of two orders of which only one can trade, such as the legs of a
+    /// one-cancels-other pair
+    pub fn either(self, other: Hold) -> Hold {
+        Hold {
+            currency: self.currency.max(other.currency),
+            items: self.items.max(other.items),
+            ..self
+        }
+    }
+}
+
+/// Every user's account, and what is held for each resting order
+#[derive(Debug, Clone, Default)]
+pub struct Accounts {
+    accounts: HashMap<String, Account>,
+    /// By order id, or for a one-cancels-other pair the lower of the two ids
+    holds: HashMap<Uuid, Hold>,
+}
+
+impl Accounts {
+    pub fn new() -> Accounts {
+        Accounts::default()
+    }
+
+    /// Accounts with these balances and nothing held
+    pub fn from_balances(balances: HashMap<String, Account>) -> Accounts {
+        let accounts = balances
+            .into_iter()
+            .map(|(user, account)| {
+                let account = Account { held: Price::default(), held_items: HashMap::new(), ..account };
+                (user, account)
+            })
+            .collect();
+
+        Accounts { accounts, holds: HashMap::new() }
+    }
+
+    pub fn get(&self, user: &str) -> Option<&Account> {
+        self.accounts.get(user)
+    }
+
+    pub fn balances(&self) -> HashMap<String, Account> {
+        self.accounts.clone()
+    }
+
+    pub fn deposit(&mut self, user: &str, amount: Price) -> Result<(), MarketError> {
+        if amount <= Price::from(0) {
+            return Err(MarketError::InvalidPrice("Deposit must be positive".to_string()));
+        }
+
+        let account = self.accounts.entry(user.to_string()).or_default();
+        account.balance = account
+            .balance
+            .checked_add(amount)
+            .ok_or(MarketError::InvalidPrice("Balance is too large".to_string()))?;
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, user: &str, amount: Price) -> Result<(), MarketError> {
+        if amount <= Price::from(0) {
+            return Err(MarketError::InvalidPrice("Withdrawal must be positive".to_string()));
+        }
+
+        let account = self.accounts.get_mut(user).ok_or(MarketError::InsufficientFunds)?;
+        if account.available() < amount {
+            return Err(MarketError::InsufficientFunds);
+        }
+        account.balance = account.balance.checked_sub(amount).unwrap_or_default();
+        Ok(())
+    }
+
+    pub fn deposit_items(&mut self, user: &str, item: &str, amount: u32) -> Result<(), MarketError> {
+        if amount < 1 {
+            return Err(MarketError::InvalidQuantity("Deposit must be at least 1".to_string()));
+        }
+
+        let account = self.accounts.entry(user.to_string()).or_default();
+        let owned = account.items.entry(item.to_string()).or_insert(0);
+        *owned = owned
+            .checked_add(amount)
+            .ok_or(MarketError::InvalidQuantity("Too many items".to_string()))?;
+        Ok(())
+    }
+
+    pub fn withdraw_items(&mut self, user: &str, item: &str, amount: u32) -> Result<(), MarketError> {
+        if amount < 1 {
+            return Err(MarketError::InvalidQuantity("Withdrawal must be at least 1".to_string()));
+        }
+
+        let account = self
+            .accounts
+            .get_mut(user)
+            .ok_or(MarketError::InsufficientItems(item.to_string()))?;
+        if account.available_items(item) < amount {
+            return Err(MarketError::InsufficientItems(item.to_string()));
+        }
+        if let Some(owned) = account.items.get_mut(item) {
+            *owned -= amount;
+        }
+        Ok(())
+    }
+
+    pub fn available(&self, user: &str) -> Price {
+        self.get(user).map(|x| x.available()).unwrap_or_default()
+    }
+
+    /// Check the user can cover `hold` on top of what is already held for them
+    pub fn check(&self, hold: &Hold) -> Result<(), MarketError> {
+        let account = self.get(&hold.user);
+        let funds = account.map(|x| x.available()).unwrap_or_default();
+        let items = account.map(|x| x.available_items(&hold.item)).unwrap_or(0);
+
+        if hold.currency > funds {
+            return Err(MarketError::InsufficientFunds);
+        }
+        if hold.items > items {
+            return Err(MarketError::InsufficientItems(hold.item.clone()));
+        }
+        Ok(())
+    }
+
+    /// Check the user can cover `hold` once `held`, already held for them, is released
+    pub fn check_replacing(&self, hold: &Hold, held: &Hold) -> Result<(), MarketError> {
+        let extra = Hold {
+            currency: hold.currency.checked_sub(held.currency).unwrap_or_default().max(Price::default()),
+            items: hold.items.saturating_sub(held.items),
+            ..hold.clone()
+        };
+        self.check(&extra)
+    }
+
+    /// Replace what is held under `key`, releasing the old hold first
+    pub fn set_hold(&mut self, key: Uuid, hold: Option<Hold>) {
+        if let Some(old) = self.holds.remove(&key) {
+            let account = self.accounts.entry(old.user.clone()).or_default();
+            account.held = account.held.checked_sub(old.currency).unwrap_or_default();
+            if let Some(held) = account.held_items.get_mut(&old.item) {
+                *held = held.saturating_sub(old.items);
+            }
+        }
+
+        if let Some(new) = hold {
+            if new.currency == Price::default() && new.items == 0 {
+                return;
+            }
+
+            let account = self.accounts.entry(new.user.clone()).or_default();
+            account.held = account.held.checked_add(new.currency).unwrap_or(account.held);
+            let held = account.held_items.entry(new.item.clone()).or_insert(0);
+            *held = held.saturating_add(new.items);
+            self.holds.insert(key, new);
+        }
+    }
+
+    /// Move currency from buyer to seller and items from seller to buyer for each trade, and
+    /// take each side's fee. Escrow should leave the buyer enough to pay, but nothing is paid
+    /// out that was not taken in: a buyer short of funds pays what it has, the price before
+    /// its fee, and the seller receives only that. Each transaction's fees are brought down
+    /// to what was actually collected or paid.
+    pub fn settle(&mut self, item: &str, transactions: &mut [Transaction]) {
+        let zero = Price::default();
+
+        for transaction in transactions.iter_mut() {
+            let value = transaction.value();
+            let (buyer_fee, seller_fee) = (transaction.buyer_fee, transaction.seller_fee);
+
+            let buyer = self.accounts.entry(transaction.buyer.clone()).or_default();
+            let paid = value.min(buyer.balance).max(zero);
+            let left = buyer.balance.checked_sub(paid).unwrap_or(zero);
+            let buyer_charged = buyer_fee.min(left).max(zero);
+            buyer.balance = left.checked_sub(buyer_charged).unwrap_or(zero);
+            let owned = buyer.items.entry(item.to_string()).or_insert(0);
+            *owned = owned.saturating_add(transaction.amount);
+
+            // A rebate to one side comes out of the fee collected from the other
+            let seller_charged = seller_fee.min(paid).max(zero);
+            let rebate = |fee: Price, collected: Price| {
+                zero.checked_sub(fee).unwrap_or(zero).min(collected).max(zero)
+            };
+            let seller_rebate = rebate(seller_fee, buyer_charged);
+            let buyer_rebate = rebate(buyer_fee, seller_charged);
+
+            let buyer = self.accounts.entry(transaction.buyer.clone()).or_default();
+            buyer.balance = buyer.balance.checked_add(buyer_rebate).unwrap_or(buyer.balance);
+
+            let proceeds = paid
+                .checked_sub(seller_charged)
+                .and_then(|x| x.checked_add(seller_rebate))
+                .unwrap_or(zero);
+            let seller = self.accounts.entry(transaction.seller.clone()).or_default();
+            seller.balance = seller.balance.checked_add(proceeds).unwrap_or(seller.balance);
+            if let Some(owned) = seller.items.get_mut(item) {
+                *owned = owned.saturating_sub(transaction.amount);
+            }
+
+            transaction.buyer_fee = match buyer_fee < zero {
+                true => zero.checked_sub(buyer_rebate).unwrap_or(zero),
+                false => buyer_charged,
+            };
+            transaction.seller_fee = match seller_fee < zero {
+                true => zero.checked_sub(seller_rebate).unwrap_or(zero),
+                false => seller_charged,
+            };
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use crate::price::Price;
+use crate::structs::{FeeSchedule, Order, OrderKind, SelfTrade};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Bound::{Excluded, Unbounded};
+
+use uuid::Uuid;
+
+/// Gaps a level can build up before it is compacted, however few orders it holds
+const MIN_GAPS: usize = 32;
+
+/// The orders at one price, first in first out. Each order keeps a numbered slot, found by
+/// id, so taking one out leaves a gap rather than shifting the ones behind it. Gaps at the
+/// front are dropped at once; the rest are cleared out by `compact` once they outnumber the
+/// orders.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Level {
+    slots: VecDeque<Option<Order>>,
+    /// Number of the slot at the front of `slots`
+    base: u64,
+    ids: HashMap<Uuid, u64>,
+}
+
+impl Level {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.slots.iter().flatten()
+    }
+
+    pub fn front(&self) -> Option<&Order> {
+        self.iter().next()
+    }
+
+    /// Number of the first slot, and one past the last, for walking the queue while it changes
+    pub fn slot_range(&self) -> (u64, u64) {
+        (self.base, self.base + self.slots.len() as u64)
+    }
+
+    pub fn push_back(&mut self, order: Order) {
+        self.ids.insert(order.id, self.base + self.slots.len() as u64);
+        self.slots.push_back(Some(order));
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&Order> {
+        self.slot(*self.ids.get(id)?)
+    }
+
+    pub fn get_mut(&mut self, id: &Uuid) -> Option<&mut Order> {
+        self.slot_mut(*self.ids.get(id)?)
+    }
+
+    /// The order in a slot, unless it is a gap
+    pub fn slot(&self, slot: u64) -> Option<&Order> {
+        let position = usize::try_from(slot.checked_sub(self.base)?).ok()?;
+        self.slots.get(position)?.as_ref()
+    }
+
+    pub fn slot_mut(&mut self, slot: u64) -> Option<&mut Order> {
+        let position = usize::try_from(slot.checked_sub(self.base)?).ok()?;
+        self.slots.get_mut(position)?.as_mut()
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<Order> {
+        let slot = *self.ids.get(id)?;
+        self.take(slot)
+    }
+
+    /// Take the order out of a slot, leaving a gap. Other slots keep their numbers.
+    pub fn take(&mut self, slot: u64) -> Option<Order> {
+        let position = usize::try_from(slot.checked_sub(self.base)?).ok()?;
+        let order = self.slots.get_mut(position)?.take()?;
+        self.ids.remove(&order.id);
+
+        while let Some(None) = self.slots.front() {
+            self.slots.pop_front();
+            self.base += 1;
+        }
+
+        Some(order)
+    }
+
+    /// Clear out the gaps if there are more of them than orders, numbering the slots afresh
+    pub fn compact(&mut self) {
+        let gaps = self.slots.len() - self.ids.len();
+        if gaps <= MIN_GAPS.max(self.ids.len()) {
+            return;
+        }
+
+        self.slots.retain(|slot| slot.is_some());
+        for (position, order) in self.slots.iter().flatten().enumerate() {
+            self.ids.insert(order.id, self.base + position as u64);
+        }
+    }
+
+    pub fn into_orders(self) -> impl Iterator<Item = Order> {
+        self.slots.into_iter().flatten()
+    }
+}
+
+/// Levels are equal if they hold the same orders in the same order, wherever the gaps are
+impl PartialEq for Level {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+/// One side of a ledger: a FIFO queue of orders per price level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookSide {
+    kind: OrderKind,
+    levels: BTreeMap<Price, Level>,
+}
+
+impl BookSide {
+    /// `kind` is BUY or SELL and decides which end of the book is the best price
+    pub fn new(kind: OrderKind) -> BookSide {
+        BookSide {
+            kind,
+            levels: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.values().map(|queue| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Oldest order at the best price
+    pub fn best(&self) -> Option<&Order> {
+        let level = match self.kind {
+            OrderKind::BUY => self.levels.values().next_back(),
+            _ => self.levels.values().next(),
+        };
+
+        level.and_then(|queue| queue.front())
+    }
+
+    /// All orders in ascending price, oldest first within a price level
+    pub fn orders(&self) -> Vec<Order> {
+        self.iter().cloned().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.levels.values().flat_map(Level::iter)
+    }
+
+    /// Queue an order at the back of its price level
+    pub fn insert(&mut self, order: Order) {
+        self.levels
+            .entry(order.price_per)
+            .or_default()
+            .push_back(order);
+    }
+
+    pub fn get(&self, price: Price, id: &Uuid) -> Option<&Order> {
+        self.levels.get(&price)?.get(id)
+    }
+
+    pub fn get_mut(&mut self, price: Price, id: &Uuid) -> Option<&mut Order> {
+        self.levels.get_mut(&price)?.get_mut(id)
+    }
+
+    pub fn remove(&mut self, price: Price, id: &Uuid) -> Option<Order> {
+        let level = self.levels.get_mut(&price)?;
+        let order = level.remove(id);
+        self.prune(price);
+        order
+    }
+
+    /// Best price with an order still live at market time `now`
+    pub fn best_price(&self, now: u64) -> Option<Price> {
+        self.by_priority()
+            .find(|(_, queue)| queue.iter().any(|x| !x.is_expired(now)))
+            .map(|(price, _)| *price)
+    }
+
+    /// How much of an incoming order is sure to trade against this side at market time `now`,
+    /// at prices `crosses` accepts. Orders that will not take the size on offer are passed
+    /// over, and the order's own resting orders are met as its self-trade prevention says.
+    pub fn fillable(&self, order: &Order, now: u64, crosses: impl Fn(Price) -> bool) -> u32 {
+        let mut left = order.amount;
+        let mut filled = 0;
+        for (price, queue) in self.by_priority() {
+            if left == 0 || !crosses(*price) {
+                break;
+            }
+
+            for resting in queue.iter().filter(|x| !x.is_expired(now)) {
+                let size = left.min(resting.amount + resting.reserve);
+                if !resting.accepts(size) {
+                    continue;
+                }
+
+                match self_trade(order, resting) {
+                    None => filled += size,
+                    Some(SelfTrade::CancelOldest) => continue,
+                    Some(SelfTrade::DecrementAndCancel) => {}
+                    Some(_) => return filled,
+                }
+                left -= size;
+            }
+        }
+
+        filled
+    }
+
+    /// How many units `budget` is sure to buy from this side for an incoming order at market
+    /// time `now`, in whole `lot`s, at prices `crosses` accepts, with the taker's `fees` on
+    /// every trade paid out of it too. Orders are passed over and met as in `fillable`.
+    pub fn affordable(
+        &self,
+        order: &Order,
+        budget: Price,
+        lot: u32,
+        fees: Option<&FeeSchedule>,
+        now: u64,
+        crosses: impl Fn(Price) -> bool,
+    ) -> u32 {
+        let lot = lot.max(1);
+        let mut left = budget;
+        let mut bought: u32 = 0;
+        for (price, queue) in self.by_priority() {
+            if left.fits(*price) < lot || !crosses(*price) {
+                break;
+            }
+
+            for resting in queue.iter().filter(|x| !x.is_expired(now)) {
+                let cost = |size: u32| {
+                    let value = price.checked_mul(size)?;
+                    let fee = fees.map(|x| x.most_as_taker(value, trades(resting, size)));
+                    value.checked_add(fee.unwrap_or_default())
+                };
+
+                // The most whole lots that fit, fees and all
+                let most = left.fits(*price).min(resting.amount + resting.reserve) / lot;
+                let (mut low, mut high) = (0, most);
+                while low < high {
+                    let mid = low + (high - low).div_ceil(2);
+                    match cost(mid * lot) {
+                        Some(cost) if cost <= left => low = mid,
+                        _ => high = mid - 1,
+                    }
+                }
+                let size = low * lot;
+                if size == 0 || !resting.accepts(size) {
+                    continue;
+                }
+
+                match self_trade(order, resting) {
+                    None => {
+                        left = cost(size)
+                            .and_then(|cost| left.checked_sub(cost))
+                            .unwrap_or_default();
+                    }
+                    Some(SelfTrade::CancelOldest) => continue,
+                    // Cancelled rather than bought, so nothing is spent on them
+                    Some(SelfTrade::DecrementAndCancel) => {}
+                    Some(_) => return bought,
+                }
+                bought = bought.saturating_add(size);
+            }
+        }
+
+        bought
+    }
+
+    /// Price levels, the one matched first coming first
+    fn by_priority(&self) -> Box<dyn Iterator<Item = (&Price, &Level)> + '_> {
+        match self.kind {
+            OrderKind::BUY => Box::new(self.levels.iter().rev()),
+            _ => Box::new(self.levels.iter()),
+        }
+    }
+
+    /// The price level matched next after the one at `after`, or the best one
+    pub(crate) fn next_level(
+        &mut self,
+        after: Option<Price>,
+    ) -> Option<(Price, &mut Level)> {
+        let level = match (self.kind, after) {
+            (OrderKind::BUY, Some(after)) => self.levels.range_mut(..after).next_back(),
+            (OrderKind::BUY, None) => self.levels.iter_mut().next_back(),
+            (_, Some(after)) => self.levels.range_mut((Excluded(after), Unbounded)).next(),
+            (_, None) => self.levels.iter_mut().next(),
+        };
+
+        level.map(|(price, queue)| (*price, queue))
+    }
+
+    /// Drop the level at `price` if nothing is left in it, or clear out its gaps
+    pub(crate) fn prune(&mut self, price: Price) {
+        match self.levels.get_mut(&price) {
+            Some(level) if level.is_empty() => {
+                self.levels.remove(&price);
+            }
+            Some(level) => level.compact(),
+            None => {}
+        }
+    }
+}
+
+/// How many trades taking `size` from `resting` makes: one, plus one for each further
+/// iceberg slice it reaches
+fn trades(resting: &Order, size: u32) -> u32 {
+    let beyond = size.saturating_sub(resting.amount);
+    let display = resting.display.unwrap_or(u32::MAX).max(1);
+    1 + beyond.div_ceil(display)
+}
+
+/// How an incoming order's self-trade prevention meets `resting`, if it is the same user's
+pub(crate) fn self_trade(order: &Order, resting: &Order) -> Option<SelfTrade> {
+    order.self_trade.filter(|_| order.user_id == resting.user_id)
+}
+
+/// Stop orders waiting for the last traded price to reach their trigger price
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TriggerBook {
+    /// Fire when the price rises to their trigger
+    buys: BTreeMap<Price, Level>,
+    /// Fire when the price falls to their trigger
+    sells: BTreeMap<Price, Level>,
+    /// The kind and trigger of each trailing stop, by id, so a trade moves them without
+    /// walking every stop
+    trailing: HashMap<Uuid, (OrderKind, Price)>,
+}
+
+impl TriggerBook {
+    pub fn new() -> TriggerBook {
+        TriggerBook::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buys.is_empty() && self.sells.is_empty()
+    }
+
+    /// Buy stops then sell stops, each in ascending trigger price
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.buys.values().flat_map(Level::iter).chain(self.sells.values().flat_map(Level::iter))
+    }
+
+    pub fn orders(&self) -> Vec<Order> {
+        self.iter().cloned().collect()
+    }
+
+    /// File a stop order under its trigger price
+    pub fn insert(&mut self, order: Order) {
+        let trigger = order.trigger_price.unwrap_or_default();
+        if order.trail.is_some() {
+            self.trailing.insert(order.id, (order.kind, trigger));
+        }
+        self.side_mut(order.kind)
+            .entry(trigger)
+            .or_default()
+            .push_back(order);
+    }
+
+    pub fn get(&self, kind: OrderKind, trigger: Price, id: &Uuid) -> Option<&Order> {
+        let side = if kind.is_buy() { &self.buys } else { &self.sells };
+        side.get(&trigger)?.get(id)
+    }
+
+    pub fn remove(&mut self, kind: OrderKind, trigger: Price, id: &Uuid) -> Option<Order> {
+        self.trailing.remove(id);
+        let side = self.side_mut(kind);
+        let level = side.get_mut(&trigger)?;
+        let order = level.remove(id);
+
+        if level.is_empty() {
+            side.remove(&trigger);
+        } else {
+            level.compact();
+        }
+
+        order
+    }
+
+    /// Take out every stop that a trade at `last` sets off, in the order they arrived
+    pub fn triggered(&mut self, last: Price) -> Vec<Order> {
+        let buys: Vec<Price> = self.buys.range(..=last).map(|(price, _)| *price).collect();
+        let sells: Vec<Price> = self.sells.range(last..).map(|(price, _)| *price).collect();
+
+        let mut fired: Vec<Order> = vec![];
+        for price in buys {
+            fired.extend(self.buys.remove(&price).into_iter().flat_map(Level::into_orders));
+        }
+        for price in sells {
+            fired.extend(self.sells.remove(&price).into_iter().flat_map(Level::into_orders));
+        }
+
+        for order in fired.iter() {
+            self.trailing.remove(&order.id);
+        }
+
+        fired.sort_by_key(|x| x.seq);
+        fired
+    }
+
+    /// Move trailing stops' triggers after a trade at `last`, returning the id, kind and new
+    /// trigger of each stop that moved
+    pub fn trail(&mut self, last: Price) -> Vec<(Uuid, OrderKind, Price)> {
+        let mut moving: Vec<(u64, Uuid, OrderKind, Price, Price)> = self
+            .trailing
+            .iter()
+            .filter_map(|(id, (kind, from))| {
+                let order = self.get(*kind, *from, id)?;
+                Some((order.seq, *id, *kind, *from, order.trailed_trigger(last)?))
+            })
+            .collect();
+        // Stops that move to the same trigger queue there in the order they arrived
+        moving.sort_by_key(|x| x.0);
+
+        let mut moved = vec![];
+        for (_, id, kind, from, to) in moving {
+            if let Some(mut order) = self.remove(kind, from, &id) {
+                order.trigger_price = Some(to);
+                self.insert(order);
+                moved.push((id, kind, to));
+            }
+        }
+
+        moved
+    }
+
+    fn side_mut(&mut self, kind: OrderKind) -> &mut BTreeMap<Price, Level> {
+        if kind.is_buy() {
+            &mut self.buys
+        } else {
+            &mut self.sells
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// Why the market refused to do what was asked
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketError {
+    /// No ledger exists for the item
+    UnknownItem(String),
+    /// The amount breaks the item's rules
+    InvalidQuantity(String),
+    /// The price breaks the item's rules
+    InvalidPrice(String),
+    /// An item spec that cannot be applied
+    InvalidSpec(String),
+    /// Order options that do not go together
+    InvalidOrder(String),
+    OrderNotFound,
+    /// The order belongs to a different user
+    NotOwner,
+    /// Trading in the item is halted
+    Halted(String),
+    /// The order's expiry is not after the market's current time
+    Expired,
+    /// The user's available balance does not cover it
+    InsufficientFunds,
+    /// The user does not have enough of the item available
+    InsufficientItems(String),
+    /// Accounts have not been enabled for the market
+    AccountsDisabled,
+    /// The order would take its user past one of the market's risk limits
+    LimitExceeded(String),
+}
+
+impl fmt::Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketError::UnknownItem(item) => write!(f, "Unknown item {}", item),
+            MarketError::InvalidQuantity(reason) => write!(f, "{}", reason),
+            MarketError::InvalidPrice(reason) => write!(f, "{}", reason),
+            MarketError::InvalidSpec(reason) => write!(f, "{}", reason),
+            MarketError::InvalidOrder(reason) => write!(f, "{}", reason),
+            MarketError::OrderNotFound => write!(f, "Order does not exist"),
+            MarketError::NotOwner => write!(f, "Order belongs to another user"),
+            MarketError::Halted(item) => write!(f, "Trading in {} is halted", item),
+            MarketError::Expired => write!(f, "Order has already expired"),
+            MarketError::InsufficientFunds => write!(f, "Insufficient funds"),
+            MarketError::InsufficientItems(item) => write!(f, "Not enough {} available", item),
+            MarketError::AccountsDisabled => write!(f, "Accounts are not enabled"),
+            MarketError::LimitExceeded(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MarketError {}
@@ -1,3 +1,7 @@
 pub mod structs;
+pub mod account;
+pub mod book;
+pub mod error;
 pub mod market;
+pub mod price;
 pub mod webassembly;
\ No newline at end of file
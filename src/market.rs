@@ -1,121 +1,1002 @@
-use crate::structs::{Order, OrderJSON, OrderKind, OrderRequest, Summary, Transaction};
-use std::collections::HashMap;
+use crate::account::{Account, Accounts, Hold};
+use crate::book::{self_trade, BookSide, Level, TriggerBook};
+use crate::error::MarketError;
+use crate::price::Price;
+use crate::structs::{
+    CancelReason, Cancellation, FeeSchedule, ItemSpec, Order, OrderJSON, OrderKind, OrderRequest,
+    PostOnly, RiskLimits, SelfTrade, Summary, TimeInForce, Transaction,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct Ledger {
-    pub buy_orders: Vec<Order>,
-    pub sell_orders: Vec<Order>,
+    buy_side: BookSide,
+    sell_side: BookSide,
+    /// Stop orders waiting on `last_price`
+    stops: TriggerBook,
+    /// Where each resting order lives, by id, and what each user has open
+    index: Index,
+    sequence: u64,
+    /// Price of the most recent trade
+    last_price: Option<Price>,
+    /// Stops filed since trigger prices were last checked without a trade
+    filed: Vec<Uuid>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LedgerJSON {
     pub buy_orders: Vec<OrderJSON>,
     pub sell_orders: Vec<OrderJSON>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_orders: Vec<OrderJSON>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_price: Option<Price>,
 }
 
 impl Ledger {
     pub fn new() -> Ledger {
         Ledger {
-            buy_orders: vec![],
-            sell_orders: vec![],
+            buy_side: BookSide::new(OrderKind::BUY),
+            sell_side: BookSide::new(OrderKind::SELL),
+            stops: TriggerBook::new(),
+            index: Index::default(),
+            sequence: 0,
+            last_price: None,
+            filed: vec![],
         }
     }
 
+    /// Stamp an incoming order with the next arrival number for this ledger
+    pub fn sequence(&mut self, order: &mut Order) {
+        order.seq = self.sequence;
+        self.sequence = self.sequence.saturating_add(1);
+    }
+
+    /// Buy orders in ascending price, oldest first within a price level
+    pub fn buy_orders(&self) -> Vec<Order> {
+        self.buy_side.orders()
+    }
+
+    /// Sell orders in ascending price, oldest first within a price level
+    pub fn sell_orders(&self) -> Vec<Order> {
+        self.sell_side.orders()
+    }
+
+    pub fn best_buy(&self) -> Option<&Order> {
+        self.buy_side.best()
+    }
+
+    pub fn best_sell(&self) -> Option<&Order> {
+        self.sell_side.best()
+    }
+
+    /// Stop orders waiting for their trigger, buys then sells, in ascending trigger price
+    pub fn stop_orders(&self) -> Vec<Order> {
+        self.stops.orders()
+    }
+
+    /// Whether no order rests or waits to trigger on this book
+    pub fn is_empty(&self) -> bool {
+        self.buy_side.is_empty() && self.sell_side.is_empty() && self.stops.is_empty()
+    }
+
+    /// How many orders a user has resting or waiting to trigger, and how many units are
+    /// left on them, hidden reserves included
+    pub fn open_by(&self, user: &str) -> (usize, u64) {
+        self.index.open.get(user).copied().unwrap_or_default()
+    }
+
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    /// Rest a BUY or SELL order on its side of the book, or file a stop order under its trigger
+    pub fn insert(&mut self, order: Order) {
+        match order.kind {
+            OrderKind::BUY => {
+                self.index.insert(&order, order.price_per);
+                self.buy_side.insert(order);
+            }
+            OrderKind::SELL => {
+                self.index.insert(&order, order.price_per);
+                self.sell_side.insert(order);
+            }
+            kind if kind.is_stop() => {
+                self.index.insert(&order, order.trigger_price.unwrap_or_default());
+                self.filed.push(order.id);
+                self.stops.insert(order);
+            }
+            _ => {}
+        }
+    }
+
+    /// Move trailing stops' triggers after a trade at `last`
+    fn trail_stops(&mut self, last: Price) {
+        for (id, kind, trigger) in self.stops.trail(last) {
+            self.index.places.insert(id, (kind, trigger));
+        }
+    }
+
+    /// Take out the stops filed since the last check that the last trade price has already
+    /// reached. Every other stop was checked against it when it traded.
+    fn reached(&mut self) -> Vec<Order> {
+        let last = match self.last_price {
+            Some(last) => last,
+            None => {
+                self.filed.clear();
+                return vec![];
+            }
+        };
+
+        let mut reached = vec![];
+        for id in std::mem::take(&mut self.filed) {
+            let (kind, trigger) = match self.index.places.get(&id) {
+                Some((kind, trigger)) if kind.is_stop() => (*kind, *trigger),
+                _ => continue,
+            };
+            let fires = if kind.is_buy() { trigger <= last } else { trigger >= last };
+            if fires {
+                reached.extend(self.stops.remove(kind, trigger, &id));
+            }
+        }
+
+        reached
+    }
+
+    /// Take every order that has expired by market time `now` off the book, along with the
+    /// other legs of any one-cancels-other pairs
+    pub fn expire(&mut self, now: u64) -> Vec<Order> {
+        let expired: Vec<Uuid> = self
+            .buy_side
+            .iter()
+            .chain(self.sell_side.iter())
+            .chain(self.stops.iter())
+            .filter(|x| x.is_expired(now))
+            .map(|x| x.id)
+            .collect();
+
+        let mut orders = vec![];
+        for id in expired {
+            if let Some(order) = self.remove(&id) {
+                let linked = self.unlink(&order);
+                orders.push(order);
+                orders.extend(linked);
+            }
+        }
+
+        orders
+    }
+
+    /// A resting order, by id
+    pub fn get(&self, id: &Uuid) -> Option<&Order> {
+        let (kind, price) = self.index.places.get(id)?;
+
+        match kind {
+            OrderKind::BUY => self.buy_side.get(*price, id),
+            OrderKind::SELL => self.sell_side.get(*price, id),
+            _ => self.stops.get(*kind, *price, id),
+        }
+    }
+
+    /// A resting limit order, by id, to change in place
+    fn get_mut(&mut self, id: &Uuid) -> Option<&mut Order> {
+        let (kind, price) = self.index.places.get(id)?;
+
+        match kind {
+            OrderKind::BUY => self.buy_side.get_mut(*price, id),
+            OrderKind::SELL => self.sell_side.get_mut(*price, id),
+            _ => None,
+        }
+    }
+
+    /// Take a resting order off the book
+    pub fn remove(&mut self, id: &Uuid) -> Option<Order> {
+        let (kind, price) = *self.index.places.get(id)?;
+
+        let order = match kind {
+            OrderKind::BUY => self.buy_side.remove(price, id),
+            OrderKind::SELL => self.sell_side.remove(price, id),
+            _ => self.stops.remove(kind, price, id),
+        }?;
+        self.index.remove(&order);
+        Some(order)
+    }
+
+    /// Take a resting order off the book, and the other leg of its pair with it, returning
+    /// the order followed by that leg if it was there
+    pub fn cancel(&mut self, id: &Uuid) -> Option<Vec<Order>> {
+        let order = self.remove(id)?;
+        let linked = self.unlink(&order);
+        Some([Some(order), linked].into_iter().flatten().collect())
+    }
+
+    /// Take the other leg of an order's one-cancels-other pair off the book, if it is there
+    fn unlink(&mut self, order: &Order) -> Option<Order> {
+        self.remove(&order.oco?)
+    }
+
+    /// The book as other traders may see it: icebergs show only their visible slice
+    pub fn to_public_json(&self) -> LedgerJSON {
+        LedgerJSON {
+            buy_orders: self.buy_side.iter().map(|x| x.to_public_json()).collect(),
+            sell_orders: self.sell_side.iter().map(|x| x.to_public_json()).collect(),
+            stop_orders: vec![],
+            last_price: self.last_price,
+        }
+    }
+
+    /// The full book, hidden reserves and all, for saving and `from_json`
     pub fn to_json(&self) -> LedgerJSON {
         LedgerJSON {
-            buy_orders: self.buy_orders.iter().map(|x| x.to_json()).collect(),
-            sell_orders: self.sell_orders.iter().map(|x| x.to_json()).collect(),
+            buy_orders: self.buy_side.orders().iter().map(|x| x.to_json()).collect(),
+            sell_orders: self
+                .sell_side
+                .orders()
+                .iter()
+                .map(|x| x.to_json())
+                .collect(),
+            stop_orders: self.stops.orders().iter().map(|x| x.to_json()).collect(),
+            last_price: self.last_price,
         }
     }
 
     pub fn from_json(ledger_json: LedgerJSON) -> Ledger {
-        Ledger {
-            buy_orders: ledger_json
-                .buy_orders
-                .into_iter()
-                .filter_map(|x| Order::from_json(x))
-                .collect(),
-            sell_orders: ledger_json
-                .sell_orders
-                .into_iter()
-                .filter_map(|x| Order::from_json(x))
-                .collect(),
+        let mut ledger = Ledger::new();
+
+        let mut orders = ledger.load_orders(ledger_json.buy_orders);
+        orders.append(&mut ledger.load_orders(ledger_json.sell_orders));
+        orders.append(&mut ledger.load_orders(ledger_json.stop_orders));
+        ledger.last_price = ledger_json.last_price;
+
+        // Queue in arrival order so each price level keeps its time priority
+        orders.sort_by_key(|x| x.seq);
+        for order in orders {
+            ledger.insert(order);
+        }
+
+        ledger
+    }
+
+    /// Parse stored orders, keeping their arrival numbers. Dumps from before
+    /// sequencing existed are numbered in the order they were listed.
+    fn load_orders(&mut self, orders: Vec<OrderJSON>) -> Vec<Order> {
+        let mut loaded = vec![];
+
+        for json in orders {
+            let seq = json.seq;
+            if let Some(mut order) = Order::from_json(json) {
+                match seq {
+                    Some(seq) => {
+                        order.seq = seq;
+                        self.sequence = self.sequence.max(seq.saturating_add(1));
+                    }
+                    None => self.sequence(&mut order),
+                }
+                loaded.push(order);
+            }
+        }
+
+        loaded
+    }
+}
+
+/// Where each order on a ledger lives, kept alongside the book so an order is found, and a
+/// user's open orders counted, without walking it
+#[derive(Clone, Default, PartialEq)]
+struct Index {
+    /// Each order's kind and price level, or trigger price for stops, by id
+    places: HashMap<Uuid, (OrderKind, Price)>,
+    /// Each user's open orders, and the units left on them
+    open: HashMap<String, (usize, u64)>,
+}
+
+impl Index {
+    fn insert(&mut self, order: &Order, price: Price) {
+        self.places.insert(order.id, (order.kind, price));
+        let (orders, units) = self.open.entry(order.user_id.clone()).or_default();
+        *orders += 1;
+        *units += (order.amount + order.reserve) as u64;
+    }
+
+    /// Forget an order taken off the book, with whatever it has left
+    fn remove(&mut self, order: &Order) {
+        if self.places.remove(&order.id).is_none() {
+            return;
+        }
+        self.reduce(&order.user_id, order.amount + order.reserve);
+        if let Some((orders, units)) = self.open.get_mut(&order.user_id) {
+            *orders = orders.saturating_sub(1);
+            if *orders == 0 && *units == 0 {
+                self.open.remove(&order.user_id);
+            }
+        }
+    }
+
+    /// Count `amount` less open for a user whose resting order traded or was cut
+    fn reduce(&mut self, user: &str, amount: u32) {
+        if let Some((_, units)) = self.open.get_mut(user) {
+            *units = units.saturating_sub(amount as u64);
         }
     }
 }
 
+impl fmt::Debug for Ledger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ledger")
+            .field("buy_orders", &self.buy_side.orders())
+            .field("sell_orders", &self.sell_side.orders())
+            .field("sequence", &self.sequence)
+            .field("stop_orders", &self.stops.orders())
+            .field("last_price", &self.last_price)
+            .finish()
+    }
+}
+
 pub struct Market {
     pub map: HashMap<String, Ledger>,
+    /// Items without a registered spec use `ItemSpec::default()`
+    specs: HashMap<String, ItemSpec>,
+    halted: HashSet<String>,
+    /// Current market time, in whatever unit the host uses
+    now: u64,
+    default_lifetime: Option<u64>,
+    /// Self-trade prevention for orders that do not choose their own
+    self_trade: Option<SelfTrade>,
+    /// Balances and escrow, once enabled
+    accounts: Option<Accounts>,
+    /// Fees for items whose spec has none of its own
+    fees: Option<FeeSchedule>,
+    limits: RiskLimits,
 }
 
 impl Market {
     pub fn new() -> Market {
         Market {
             map: HashMap::new(),
+            specs: HashMap::new(),
+            halted: HashSet::new(),
+            now: 0,
+            default_lifetime: None,
+            self_trade: None,
+            accounts: None,
+            fees: None,
+            limits: RiskLimits::default(),
         }
     }
 
-    pub fn place_order(&mut self, order_request: OrderRequest) -> Summary {
-        let item = order_request.item;
-        let order = order_request.order;
+    /// Set the trading rules for an item. Specs are configuration rather than book state,
+    /// so they are not part of `to_json` and need registering again after a load.
+    pub fn register_item(&mut self, item: String, spec: ItemSpec) -> Result<(), MarketError> {
+        spec.check()?;
+        self.specs.insert(item.to_uppercase(), spec);
+        Ok(())
+    }
 
-        let mut summary: Summary = Summary::new(item.clone());
+    pub fn item_spec(&self, item: &str) -> ItemSpec {
+        self.specs
+            .get(&item.to_uppercase())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Stop accepting orders for an item. Resting orders can still be cancelled.
+    pub fn halt(&mut self, item: String) {
+        self.halted.insert(item.to_uppercase());
+    }
+
+    pub fn resume(&mut self, item: String) {
+        self.halted.remove(&item.to_uppercase());
+    }
+
+    /// Current market time. The engine has no clock of its own: time moves with
+    /// `set_time` and `expire_orders`, and never backwards.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Move market time forward without sweeping the book
+    pub fn set_time(&mut self, now: u64) {
+        self.now = self.now.max(now);
+    }
+
+    /// Limit orders placed without an expiry get one `lifetime` after the current market time.
+    /// `None`, the default, lets them rest until filled or cancelled.
+    pub fn set_default_lifetime(&mut self, lifetime: Option<u64>) {
+        self.default_lifetime = lifetime;
+    }
 
-        if !self.map.contains_key(&item) {
-            // insert into ledger
-            let mut ledger = Ledger::new();
-            match order.kind {
-                OrderKind::BUY => ledger.buy_orders.push(order.clone()),
-                OrderKind::SELL => ledger.sell_orders.push(order.clone()),
-                _ => { /* Do nothing */ }
+    /// Self-trade prevention for orders placed without their own `self_trade`.
+    /// `None`, the default, lets users trade with themselves.
+    pub fn set_self_trade_prevention(&mut self, mode: Option<SelfTrade>) {
+        self.self_trade = mode;
+    }
+
+    /// Fees for every item without its own in its spec. `None`, the default, charges nothing.
+    pub fn set_fees(&mut self, fees: Option<FeeSchedule>) -> Result<(), MarketError> {
+        if let Some(fees) = fees {
+            fees.check()?;
+        }
+        self.fees = fees;
+        Ok(())
+    }
+
+    /// Fees charged on an item's trades, if any
+    pub fn fee_schedule(&self, item: &str) -> Option<FeeSchedule> {
+        self.item_spec(item).fees.or(self.fees)
+    }
+
+    /// Caps on what each user can have open. The default has none.
+    pub fn set_risk_limits(&mut self, limits: RiskLimits) -> Result<(), MarketError> {
+        limits.check()?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Move market time forward to `now` and take every expired order off the book,
+    /// returning them by item. Matching already skips orders that have expired.
+    pub fn expire_orders(&mut self, now: u64) -> HashMap<String, Vec<Order>> {
+        self.set_time(now);
+
+        let mut expired = HashMap::new();
+        for (item, ledger) in self.map.iter_mut() {
+            let orders = ledger.expire(self.now);
+            if !orders.is_empty() {
+                expired.insert(item.clone(), orders);
             }
-            self.map.insert(item, ledger);
-            summary.created = Some(order);
+        }
+
+        for (item, orders) in expired.iter() {
+            self.hold(item, orders);
+        }
+
+        expired
+    }
+
+    pub fn place_order(&mut self, order_request: OrderRequest) -> Result<Summary, MarketError> {
+        let (item, mut order) = self.prepare(order_request)?;
+        let budgeted = order.budget.is_some();
+        self.check_limits(&item, &mut order, None, None)?;
+        self.back(&item, &mut order)?;
+
+        let mut summary = self.submit(item, order);
+        if !budgeted {
+            // Any budget came from the notional limit or the balance, not the user, so there
+            // is none to report on
+            summary.spent = None;
+            summary.budget_left = None;
+        }
+        self.settle(&mut summary);
+        Ok(summary)
+    }
+
+    /// Place two orders for the same item and user as a one-cancels-other pair: as soon as
+    /// either trades or leaves the book, the other is cancelled. Each leg must be able to
+    /// rest, so is a good-till-cancelled limit order or a stop order. The second leg is only
+    /// placed if the first rests untouched, and is reported in the summary's `linked`;
+    /// otherwise it is reported cancelled as `Linked`.
+    pub fn place_oco(&mut self, first: OrderRequest, second: OrderRequest) -> Result<Summary, MarketError> {
+        let (item, mut first) = self.prepare(first)?;
+        let (second_item, mut second) = self.prepare(second)?;
+
+        if item != second_item || first.user_id != second.user_id {
+            return Err(MarketError::InvalidOrder(
+                "Both legs of a one-cancels-other pair must be for the same item and user".to_string(),
+            ));
+        }
+        for leg in [&first, &second] {
+            let rests = leg.kind.is_stop() || leg.kind == OrderKind::BUY || leg.kind == OrderKind::SELL;
+            if !rests || leg.time_in_force != TimeInForce::GoodTillCancelled || leg.bracket.is_some() {
+                return Err(MarketError::InvalidOrder(
+                    "One-cancels-other legs must be good-till-cancelled limit or stop orders".to_string(),
+                ));
+            }
+        }
+
+        self.check_limits(&item, &mut first, None, None)?;
+        self.check_limits(&item, &mut second, Some(&first), None)?;
+
+        // Only one leg can trade, so each only needs backing on its own
+        self.back(&item, &mut first)?;
+        self.back(&item, &mut second)?;
+
+        first.oco = Some(second.id);
+        second.oco = Some(first.id);
+
+        let first_id = first.id;
+        let mut summary = self.submit(item.clone(), first);
+
+        // A stop leg can fire as soon as it is placed, and leave the book without trading
+        let rests = self.map.get(&item).is_some_and(|ledger| ledger.get(&first_id).is_some());
+        if summary.transactions.is_empty() && rests {
+            summary.linked.push(second.clone());
+            summary.absorb(self.submit(item, second));
         } else {
-            // update ledger
+            summary.cancelled.push(Cancellation::new(second, CancelReason::Linked));
+        }
+
+        self.settle(&mut summary);
+        Ok(summary)
+    }
+
+    /// Change the amount and price of a resting limit order. `new_amount` counts an
+    /// iceberg's reserve. Cutting the amount at the same price keeps the order's place in
+    /// the queue; anything else sends it to the back of its new price level, and a new price
+    /// that crosses the book trades like a new order. The order keeps its id.
+    pub fn amend_order(&mut self, order_id: Uuid, new_amount: u32, new_price: Price) -> Result<Summary, MarketError> {
+        let (item, resting) = self
+            .map
+            .iter()
+            .find_map(|(item, ledger)| Some((item.clone(), ledger.get(&order_id)?.clone())))
+            .ok_or(MarketError::OrderNotFound)?;
+
+        if resting.kind != OrderKind::BUY && resting.kind != OrderKind::SELL {
+            return Err(MarketError::InvalidOrder("Only resting limit orders can be amended".to_string()));
+        }
+
+        let mut amended = resting.clone();
+        amended.amount = new_amount;
+        amended.reserve = 0;
+        amended.price_per = new_price;
 
-            let ledger = &mut self.map.get_mut(&item).unwrap();
+        let request = OrderRequest { item: item.clone(), order: amended };
+        let (item, mut amended) = self.prepare(request)?;
+        amended.expires_at = resting.expires_at;
+        self.check_limits(&item, &mut amended, None, Some(&resting))?;
 
-            // transact
-            match order.kind {
-                OrderKind::BUY => buy(order, ledger, &mut summary),
-                OrderKind::SELL => sell(order, ledger, &mut summary),
-                OrderKind::MARKET_BUY => market_buy(order, ledger, &mut summary),
-                OrderKind::MARKET_SELL => market_sell(order, ledger, &mut summary),
+        if let Some(accounts) = &self.accounts {
+            let fees = self.fee_schedule(&item);
+            let held = Hold::for_order(&item, &resting, fees.as_ref());
+            accounts.check_replacing(&Hold::for_order(&item, &amended, fees.as_ref()), &held)?;
+        }
+
+        let total = resting.amount + resting.reserve;
+        let ledger = self.map.get_mut(&item).ok_or(MarketError::OrderNotFound)?;
+
+        if amended.price_per == resting.price_per && amended.amount <= total {
+            let order = ledger.get_mut(&order_id).ok_or(MarketError::OrderNotFound)?;
+
+            // Cut the hidden reserve before the slice on show
+            let cut = total - amended.amount;
+            let from_reserve = cut.min(order.reserve);
+            order.reserve -= from_reserve;
+            order.amount -= cut - from_reserve;
+            let order = order.clone();
+            ledger.index.reduce(&order.user_id, cut);
+
+            let mut summary = Summary::new(item);
+            summary.to_update.push(order);
+            self.settle(&mut summary);
+            return Ok(summary);
+        }
+
+        ledger.remove(&order_id);
+        let mut summary = self.submit(item.clone(), amended);
+        self.settle(&mut summary);
+        // It may have traded away entirely, leaving nothing in the summary to release
+        self.hold(&item, [&resting]);
+        Ok(summary)
+    }
+
+    /// Check an order against the market and its item's rules, and put it in the shape it
+    /// will be matched in
+    fn prepare(&self, order_request: OrderRequest) -> Result<(String, Order), MarketError> {
+        let item = order_request.item;
+        let mut order = order_request.order;
+
+        if self.halted.contains(&item) {
+            return Err(MarketError::Halted(item));
+        }
+
+        if order.trail.is_some() && order.kind != OrderKind::STOP_BUY && order.kind != OrderKind::STOP_SELL {
+            return Err(MarketError::InvalidOrder(
+                "Only STOP_BUY and STOP_SELL orders can trail".to_string(),
+            ));
+        }
+
+        // A trailing stop starts out behind the last trade, unless given a tighter trigger
+        if let Some(last) = self.map.get(&item).and_then(|ledger| ledger.last_price) {
+            if let Some(trigger) = order.trailed_trigger(last) {
+                order.trigger_price = Some(trigger);
+            }
+        }
+
+        if order.slippage.is_some() && order.kind.is_limit() {
+            return Err(MarketError::InvalidOrder(
+                "Only market and stop orders take a slippage limit".to_string(),
+            ));
+        }
+
+        if let Some(budget) = order.budget {
+            if order.kind != OrderKind::MARKET_BUY {
+                return Err(MarketError::InvalidOrder("Only market buys take a budget".to_string()));
+            }
+            if budget <= Price::from(0) {
+                return Err(MarketError::InvalidPrice("Budget must be positive".to_string()));
+            }
+        }
+
+        if order.kind.is_stop() != order.trigger_price.is_some() {
+            let reason = if !order.kind.is_stop() {
+                "Only stop orders take a trigger price"
+            } else if order.trail.is_some() {
+                "Trailing stops need a trigger price until the item has traded"
+            } else {
+                "Stop orders need a trigger price"
             };
+            return Err(MarketError::InvalidOrder(reason.to_string()));
         }
 
-        summary
+        let rests = order.kind == OrderKind::BUY || order.kind == OrderKind::SELL;
+        let good_till_cancelled = rests && order.time_in_force == TimeInForce::GoodTillCancelled;
+        if order.post_only.is_some() && !good_till_cancelled {
+            return Err(MarketError::InvalidOrder(
+                "Only good-till-cancelled limit orders can be post-only".to_string(),
+            ));
+        }
+        if order.display.is_some() && !good_till_cancelled {
+            return Err(MarketError::InvalidOrder(
+                "Only good-till-cancelled limit orders can be icebergs".to_string(),
+            ));
+        }
+        if order.display.is_some() && (order.all_or_none || order.min_fill.is_some()) {
+            return Err(MarketError::InvalidOrder(
+                "Icebergs cannot be all-or-none or have a minimum fill".to_string(),
+            ));
+        }
+
+        let spec = self.item_spec(&item);
+
+        // Quote in the item's decimals, never beyond the trader's own limit, so the
+        // item's rules apply to the price that actually rests. Triggers round so they
+        // fire no sooner than asked, and a bracket's exits like orders on the other side.
+        let buy = order.kind.is_buy();
+        let round = |price: Price, down: bool| {
+            let rounded = if down { price.floor(spec.decimals) } else { price.ceil(spec.decimals) };
+            rounded.unwrap_or(price)
+        };
+        order.price_per = round(order.price_per, buy);
+        order.trigger_price = order.trigger_price.map(|x| round(x, !buy));
+        if let Some(bracket) = order.bracket.as_mut() {
+            bracket.take_profit = round(bracket.take_profit, !buy);
+            bracket.stop_loss = round(bracket.stop_loss, buy);
+        }
+
+        spec.validate(&order)?;
+
+        if order.self_trade.is_none() {
+            order.self_trade = self.self_trade;
+        }
+
+        if let Some((take_profit, stop_loss)) = order.exits(order.amount) {
+            let ordered = match order.kind.is_buy() {
+                true => stop_loss.trigger_price < Some(take_profit.price_per),
+                false => stop_loss.trigger_price > Some(take_profit.price_per),
+            };
+            if !ordered {
+                return Err(MarketError::InvalidOrder(
+                    "A bracket's stop loss must be on the losing side of its take profit".to_string(),
+                ));
+            }
+            spec.validate(&take_profit)?;
+            spec.validate(&stop_loss)?;
+        }
+
+        if rests && order.expires_at.is_none() && order.time_in_force == TimeInForce::GoodTillCancelled {
+            order.expires_at = self
+                .default_lifetime
+                .map(|lifetime| self.now.saturating_add(lifetime));
+        }
+
+        if order.is_expired(self.now) {
+            return Err(MarketError::Expired);
+        }
+
+        Ok((item, order))
     }
 
-    pub fn cancel_order(&mut self, item: String, order: Order) -> Option<Order> {
-        // Create an empty ptr
-        let mut orders: Option<&mut Vec<Order>> = None;
+    /// Match a prepared order, or put it on the book
+    fn submit(&mut self, item: String, order: Order) -> Summary {
+        let mut order = order;
+        let mut summary: Summary = Summary::new(item.clone());
+        summary.fee_schedule = self.fee_schedule(&item);
+        let spec = self.item_spec(&item);
+
+        // A market order has nothing to trade against in a book that does not exist yet,
+        // and opening one for it would leave an empty ledger behind
+        let is_market_order = order.kind == OrderKind::MARKET_BUY || order.kind == OrderKind::MARKET_SELL;
+        if is_market_order && !self.map.contains_key(&item) {
+            summary.spent = order.budget.map(|_| Price::default());
+            summary.budget_left = order.budget;
+            summary.cancelled.push(Cancellation::new(order, CancelReason::NoLiquidity));
+            return summary;
+        }
 
-        match self.map.get_mut(&item.to_uppercase()) {
-            Some(ledger) => {
-                match order.kind {
-                    // Move ledger to ptr
-                    OrderKind::BUY => orders = Some(&mut ledger.buy_orders),
-                    OrderKind::SELL => orders = Some(&mut ledger.sell_orders),
-                    _ => { /* Do nothing */ }
+        // Nor does a new item's book join the market until something rests on it
+        let now = self.now;
+        let mut opened = Ledger::new();
+        let ledger = match self.map.get_mut(&item) {
+            Some(ledger) => ledger,
+            None => &mut opened,
+        };
+        ledger.sequence(&mut order);
+
+        if let Some(post_only) = order.post_only {
+            if !post(&mut order, post_only, ledger, &spec, now) {
+                summary.cancelled.push(Cancellation::new(order, CancelReason::PostOnly));
+                return summary;
+            }
+        }
+
+        let budget = order.budget;
+        if let Some(budget) = budget {
+            let requested = order.amount;
+            size_by_budget(&mut order, budget, ledger, &spec, summary.fee_schedule.as_ref(), now);
+
+            if order.amount == 0 {
+                let reason = match ledger.sell_side.best_price(now) {
+                    Some(_) => CancelReason::Budget,
+                    None => CancelReason::NoLiquidity,
                 };
+                order.amount = requested;
+                summary.spent = Some(Price::default());
+                summary.budget_left = Some(budget);
+                summary.cancelled.push(Cancellation::new(order, reason));
+                return summary;
             }
-            None => { /* Do nothing */ }
         }
 
-        // Modify ledger via ptr
-        if let Some(order_list) = orders {
-            for i in 0..order_list.len() {
-                if order_list[i].id == order.id {
-                    return Some(order_list.remove(i));
-                }
+        execute(order, ledger, &mut summary, now);
+
+        if let Some(budget) = budget {
+            let spent = spending(&summary);
+            summary.budget_left = budget.checked_sub(spent);
+            summary.spent = Some(spent);
+        }
+
+        trigger_stops(ledger, &mut summary, now, 0);
+        settle_groups(ledger, &mut summary, now);
+
+        if !opened.is_empty() {
+            self.map.insert(item, opened);
+        }
+
+        summary
+    }
+
+    /// Cancel a resting order on behalf of its owner, along with the other leg of its pair.
+    /// Returns what was left of the order, then of the other leg if it was on the book.
+    pub fn cancel_order(&mut self, item: String, order: Order) -> Result<Vec<Order>, MarketError> {
+        let item = item.to_uppercase();
+        let ledger = self
+            .map
+            .get_mut(&item)
+            .ok_or_else(|| MarketError::UnknownItem(item.clone()))?;
+
+        let cancelled = match ledger.get(&order.id) {
+            Some(resting) if resting.user_id != order.user_id => Err(MarketError::NotOwner),
+            Some(_) => ledger.cancel(&order.id).ok_or(MarketError::OrderNotFound),
+            None => Err(MarketError::OrderNotFound),
+        }?;
+
+        self.hold(&item, &cancelled);
+        Ok(cancelled)
+    }
+
+    /// Cancel a resting order by id, returning what was left of it. The other leg of a
+    /// one-cancels-other pair, named by the order's `oco`, goes with it and is returned next.
+    pub fn cancel_by_id(&mut self, item: String, order_id: Uuid) -> Result<Vec<Order>, MarketError> {
+        let item = item.to_uppercase();
+        let cancelled = match self.map.get_mut(&item) {
+            Some(ledger) => ledger.cancel(&order_id).ok_or(MarketError::OrderNotFound),
+            None => Err(MarketError::UnknownItem(item.clone())),
+        }?;
+
+        self.hold(&item, &cancelled);
+        Ok(cancelled)
+    }
+
+    /// Cancel a resting order by id without knowing its item. Checks each ledger's id index.
+    pub fn cancel(&mut self, order_id: Uuid) -> Result<Vec<Order>, MarketError> {
+        let (item, cancelled) = self
+            .map
+            .iter_mut()
+            .find_map(|(item, ledger)| Some((item.clone(), ledger.cancel(&order_id)?)))
+            .ok_or(MarketError::OrderNotFound)?;
+
+        self.hold(&item, &cancelled);
+        Ok(cancelled)
+    }
+
+    /// Start keeping accounts. From then on an order needs its user to have the funds or
+    /// items to back it, which are held in escrow while it rests, and every trade moves
+    /// value between the accounts involved. Orders already on the book are held for too.
+    pub fn enable_accounts(&mut self) {
+        self.load_accounts(HashMap::new());
+    }
+
+    /// Keep accounts starting from these balances, as saved from `accounts_to_json`.
+    /// What is held is worked out again from the book.
+    pub fn load_accounts(&mut self, balances: HashMap<String, Account>) {
+        self.accounts = Some(Accounts::from_balances(balances));
+
+        let resting: Vec<(String, Vec<Order>)> = self
+            .map
+            .iter()
+            .map(|(item, ledger)| {
+                let mut orders = ledger.buy_orders();
+                orders.extend(ledger.sell_orders());
+                orders.extend(ledger.stop_orders());
+                (item.clone(), orders)
+            })
+            .collect();
+
+        for (item, orders) in resting.iter() {
+            self.hold(item, orders);
+        }
+    }
+
+    /// Every account, for saving. `None` unless accounts are enabled.
+    pub fn accounts_to_json(&self) -> Option<HashMap<String, Account>> {
+        self.accounts.as_ref().map(|x| x.balances())
+    }
+
+    pub fn account(&self, user: &str) -> Option<&Account> {
+        self.accounts.as_ref()?.get(user)
+    }
+
+    pub fn deposit(&mut self, user: String, amount: Price) -> Result<(), MarketError> {
+        self.accounts_mut()?.deposit(&user, amount)
+    }
+
+    /// Take currency out of an account. Only what is not held for resting orders can go.
+    pub fn withdraw(&mut self, user: String, amount: Price) -> Result<(), MarketError> {
+        self.accounts_mut()?.withdraw(&user, amount)
+    }
+
+    pub fn deposit_items(&mut self, user: String, item: String, amount: u32) -> Result<(), MarketError> {
+        self.accounts_mut()?.deposit_items(&user, &item.to_uppercase(), amount)
+    }
+
+    /// Take items out of an account. Only what is not held for resting orders can go.
+    pub fn withdraw_items(&mut self, user: String, item: String, amount: u32) -> Result<(), MarketError> {
+        self.accounts_mut()?.withdraw_items(&user, &item.to_uppercase(), amount)
+    }
+
+    fn accounts_mut(&mut self) -> Result<&mut Accounts, MarketError> {
+        self.accounts.as_mut().ok_or(MarketError::AccountsDisabled)
+    }
+
+    /// Check an order keeps its user within the risk limits, alongside `beside`, the other
+    /// leg of a pair being placed, and in place of `replacing`, an order being amended.
+    /// Orders that can rest count as open until they leave the book, however much of them
+    /// trades at once. A market buy without a budget is given the notional limit as one.
+    /// Open orders are counted from each ledger's index rather than by walking the book.
+    fn check_limits(
+        &self,
+        item: &str,
+        order: &mut Order,
+        beside: Option<&Order>,
+        replacing: Option<&Order>,
+    ) -> Result<(), MarketError> {
+        let limits = self.limits;
+        let ledger = self.map.get(item);
+
+        if let Some(max) = limits.max_notional {
+            if notional(order, ledger, self.now) > max {
+                return Err(MarketError::LimitExceeded(format!(
+                    "Order is worth more than the limit of {}",
+                    max
+                )));
+            }
+            if order.kind == OrderKind::MARKET_BUY && order.budget.is_none() {
+                order.budget = Some(max);
+            }
+        }
+
+        let opens = order.kind.is_stop()
+            || (order.kind.is_limit() && order.time_in_force == TimeInForce::GoodTillCancelled);
+        if !opens {
+            return Ok(());
+        }
+
+        let user = order.user_id.as_str();
+        let adding = [Some(&*order), beside].into_iter().flatten();
+
+        if let Some(max) = limits.max_open_orders {
+            let open: usize = self.map.values().map(|x| x.open_by(user).0).sum();
+            let open = open.saturating_sub(replacing.map_or(0, |_| 1));
+            if open + adding.clone().count() > max as usize {
+                return Err(MarketError::LimitExceeded(format!(
+                    "Too many open orders; the limit is {}",
+                    max
+                )));
             }
         }
 
-        None
+        if let Some(max) = limits.max_open_quantity {
+            let open = ledger.map_or(0, |x| x.open_by(user).1);
+            let open = open.saturating_sub(replacing.map_or(0, |x| (x.amount + x.reserve) as u64));
+            let open: u64 = open + adding.map(|x| (x.amount + x.reserve) as u64).sum::<u64>();
+            if open > max as u64 {
+                return Err(MarketError::LimitExceeded(format!(
+                    "Open quantity in {} would be over the limit of {}",
+                    item, max
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// With accounts, check the user can back an order, and size a market buy by what they
+    /// can spend
+    fn back(&self, item: &str, order: &mut Order) -> Result<(), MarketError> {
+        let accounts = match &self.accounts {
+            Some(accounts) => accounts,
+            None => return Ok(()),
+        };
+
+        if order.kind == OrderKind::STOP_BUY {
+            return Err(MarketError::InvalidOrder(
+                "Stop market buys cannot be held in escrow; use a stop-limit buy".to_string(),
+            ));
+        }
+        if order.bracket.is_some() && !order.kind.is_buy() {
+            return Err(MarketError::InvalidOrder(
+                "With accounts, only buy orders can carry a bracket".to_string(),
+            ));
+        }
+
+        let fees = self.fee_schedule(item);
+        if order.kind == OrderKind::MARKET_BUY {
+            let available = accounts.available(&order.user_id);
+            order.budget = Some(order.budget.map_or(available, |budget| budget.min(available)));
+        }
+
+        accounts.check(&Hold::for_order(item, order, fees.as_ref()))
+    }
+
+    /// With accounts, move value for a placement's trades, then bring what is held in line
+    /// with what is left on the book of every order it touched
+    fn settle(&mut self, summary: &mut Summary) {
+        if let Some(accounts) = self.accounts.as_mut() {
+            accounts.settle(&summary.key, &mut summary.transactions);
+        }
+
+        let touched = summary
+            .created
+            .iter()
+            .chain(summary.to_update.iter())
+            .chain(summary.cancelled.iter().map(|x| &x.order))
+            .chain(summary.triggered.iter())
+            .chain(summary.linked.iter());
+        self.hold(&summary.key, touched);
+    }
+
+    /// Hold what each of these orders, or the one-cancels-other pair it is in, still needs
+    /// for what is left of it on the book
+    fn hold<'a>(&mut self, item: &str, orders: impl IntoIterator<Item = &'a Order>) {
+        let accounts = match self.accounts.as_mut() {
+            Some(accounts) => accounts,
+            None => return,
+        };
+        let ledger = self.map.get(item);
+        let fees = self.specs.get(item).and_then(|spec| spec.fees).or(self.fees);
+
+        for order in orders {
+            let key = order.oco.map_or(order.id, |other| other.min(order.id));
+            let hold = [Some(order.id), order.oco]
+                .into_iter()
+                .flatten()
+                .filter_map(|id| ledger?.get(&id))
+                .map(|x| Hold::for_order(item, x, fees.as_ref()))
+                .reduce(Hold::either);
+            accounts.set_hold(key, hold);
+        }
     }
 
     pub fn query_ledger(&mut self, item: String) -> Option<Ledger> {
@@ -131,7 +1012,7 @@ impl Market {
         let result = self.map.get(&item.to_uppercase());
 
         match result {
-            Some(ledger) => return ledger.buy_orders.last(),
+            Some(ledger) => return ledger.best_buy(),
             None => return None,
         }
     }
@@ -140,7 +1021,7 @@ impl Market {
         let result = self.map.get(&item.to_uppercase());
 
         match result {
-            Some(ledger) => return ledger.sell_orders.first(),
+            Some(ledger) => return ledger.best_sell(),
             None => return None,
         }
     }
@@ -164,236 +1045,558 @@ impl Market {
 
         Market {
             map: map,
+            specs: HashMap::new(),
+            halted: HashSet::new(),
+            now: 0,
+            default_lifetime: None,
+            self_trade: None,
+            accounts: None,
+            fees: None,
+            limits: RiskLimits::default(),
         }
     }
 }
 
-fn buy(order: Order, ledger: &mut Ledger, summary: &mut Summary) {
-    let buy_orders: &mut Vec<Order> = &mut ledger.buy_orders;
-    let sell_orders: &mut Vec<Order> = &mut ledger.sell_orders;
-
-    let mut order = order;
-    let end = sell_orders.binary_search(&order).unwrap_or_else(|e| e);
+/// Trade as much as possible between an incoming and a resting order, returning the amount traded
+fn fill(order: &mut Order, resting: &mut Order) -> u32 {
+    let amount = order.amount.min(resting.amount);
+    order.amount -= amount;
+    resting.amount -= amount;
+    amount
+}
 
-    let mut to_remove = vec![];
+/// Match an order against the book, or file it away if it is a stop order
+fn execute(order: Order, ledger: &mut Ledger, summary: &mut Summary, now: u64) {
+    match order.kind {
+        OrderKind::BUY => buy(order, ledger, summary, now),
+        OrderKind::SELL => sell(order, ledger, summary, now),
+        OrderKind::MARKET_BUY => market_buy(order, ledger, summary, now),
+        OrderKind::MARKET_SELL => market_sell(order, ledger, summary, now),
+        _ => {
+            ledger.insert(order.clone());
+            summary.created = Some(order);
+        }
+    }
+}
 
-    // low to high
-    for i in 0..end {
-        if order.amount < 1 {
+/// Move trailing stops after each trade in the summary from `from` on, and activate the stop
+/// orders each one reaches, then do the same for their own trades in turn. With no new trades,
+/// only stops filed since are checked against the last trade price. Stop-limit orders that rest
+/// once activated are reported in `to_update`, so `created` still means the order that was
+/// placed.
+fn trigger_stops(ledger: &mut Ledger, summary: &mut Summary, now: u64, from: usize) {
+    let created = summary.created.take();
+    let mut from = from;
+
+    loop {
+        let prices: Vec<Price> = summary.transactions[from..].iter().map(|x| x.price_per).collect();
+        from = summary.transactions.len();
+
+        let mut triggered = if prices.is_empty() { ledger.reached() } else { vec![] };
+        for last in prices {
+            ledger.last_price = Some(last);
+            ledger.trail_stops(last);
+            triggered.extend(ledger.stops.triggered(last));
+        }
+        if triggered.is_empty() {
             break;
         }
+        triggered.sort_by_key(|x| x.seq);
 
-        if sell_orders[i].price_per <= order.price_per {
-            let amount;
-            if sell_orders[i].amount > order.amount {
-                amount = order.amount;
-                sell_orders[i].amount -= order.amount;
-                order.amount = 0;
-            } else if sell_orders[i].amount < order.amount {
-                amount = sell_orders[i].amount;
-                order.amount -= sell_orders[i].amount;
-                sell_orders[i].amount = 0;
-            } else {
-                amount = order.amount;
-                sell_orders[i].amount = 0;
-                order.amount = 0;
+        for stop in triggered {
+            ledger.index.remove(&stop);
+
+            if stop.is_expired(now) {
+                summary.cancelled.push(Cancellation::new(stop, CancelReason::Expired));
+                continue;
+            }
+
+            summary.triggered.push(stop.clone());
+
+            let mut order = stop;
+            order.kind = match order.kind {
+                OrderKind::STOP_BUY => OrderKind::MARKET_BUY,
+                OrderKind::STOP_SELL => OrderKind::MARKET_SELL,
+                OrderKind::STOP_LIMIT_BUY => OrderKind::BUY,
+                _ => OrderKind::SELL,
+            };
+            // It reaches the book now, behind everything already there
+            ledger.sequence(&mut order);
+
+            execute(order, ledger, summary, now);
+            if let Some(rested) = summary.created.take() {
+                summary.to_update.push(rested);
             }
+        }
+    }
+
+    restore_created(summary, created);
+}
 
-            if sell_orders[i].amount < 1 {
-                to_remove.push(i);
+/// Cancel the other leg of every one-cancels-other pair that traded or was cancelled, and
+/// place the exits of every bracket entry that traded, until it sets nothing else off.
+/// Exits that rest are reported in `to_update`, like activated stops.
+fn settle_groups(ledger: &mut Ledger, summary: &mut Summary, now: u64) {
+    let created = summary.created.take();
+    let mut checked = 0;
+
+    loop {
+        // A linked cancellation points back at the leg that caused it, which may still rest
+        let mut links: Vec<Uuid> = summary.cancelled[checked..]
+            .iter()
+            .filter(|x| x.reason != CancelReason::Linked)
+            .filter_map(|x| x.order.oco)
+            .collect();
+        checked = summary.cancelled.len();
+
+        // The exits for an entry cover everything it traded in this placement
+        let mut filled: Vec<(Order, u32)> = vec![];
+        for (order, amount) in std::mem::take(&mut summary.group_fills) {
+            match filled.iter_mut().find(|(x, _)| x.id == order.id) {
+                Some((_, total)) => *total += amount,
+                None => filled.push((order, amount)),
             }
+        }
 
-            // Buyer sets transaction price
-            let transaction = Transaction::new(
-                order.user_id.clone(),
-                sell_orders[i].user_id.clone(),
-                amount,
-                order.price_per,
-            );
-            summary.transactions.push(transaction);
-            summary.to_update.push(sell_orders[i].clone());
-        } else {
+        if links.is_empty() && filled.is_empty() {
             break;
         }
-    }
 
-    to_remove.sort();
-    to_remove.reverse();
-    for i in 0..to_remove.len() {
-        sell_orders.remove(to_remove[i]);
-    }
+        let mut exits = vec![];
+        for (order, amount) in filled {
+            links.extend(order.oco);
+            if let Some((take_profit, stop_loss)) = order.exits(amount) {
+                exits.push(take_profit);
+                exits.push(stop_loss);
+            }
+        }
 
-    if order.amount < 1 {
-        return;
+        for id in links {
+            if let Some(other) = ledger.remove(&id) {
+                summary.cancelled.push(Cancellation::new(other, CancelReason::Linked));
+            }
+        }
+
+        let from = summary.transactions.len();
+        for mut exit in exits {
+            summary.linked.push(exit.clone());
+            ledger.sequence(&mut exit);
+            execute(exit, ledger, summary, now);
+            if let Some(rested) = summary.created.take() {
+                summary.to_update.push(rested);
+            }
+        }
+
+        trigger_stops(ledger, summary, now, from);
     }
 
-    // Add our buy order to the buy ledger
-    let pos = buy_orders.binary_search(&order).unwrap_or_else(|e| e);
-    buy_orders.insert(pos, order.clone());
-    summary.created = Some(order);
+    restore_created(summary, created);
 }
 
-fn sell(order: Order, ledger: &mut Ledger, summary: &mut Summary) {
-    let buy_orders: &mut Vec<Order> = &mut ledger.buy_orders;
-    let sell_orders: &mut Vec<Order> = &mut ledger.sell_orders;
+/// Put back the order a placement created, unless it is a stop that has since fired: it has
+/// left the trigger book, and what it did is under `triggered`
+fn restore_created(summary: &mut Summary, created: Option<Order>) {
+    summary.created = created.filter(|x| !summary.triggered.iter().any(|stop| stop.id == x.id));
+}
 
-    let mut order = order;
-    let end = buy_orders.binary_search(&order).unwrap_or_else(|e| e);
+/// The most an order could be worth: its price, or a stop's trigger, times its amount. A
+/// market sell can fetch no more than the best bid for each unit, and a market buy with a
+/// budget spends no more than that. One without a budget pays at least the best offer.
+fn notional(order: &Order, ledger: Option<&Ledger>, now: u64) -> Price {
+    let amount = order.amount + order.reserve;
+    let price = match order.kind {
+        OrderKind::MARKET_BUY => match order.budget {
+            Some(budget) => return budget,
+            None => ledger.and_then(|x| x.sell_side.best_price(now)),
+        },
+        OrderKind::MARKET_SELL => ledger.and_then(|x| x.buy_side.best_price(now)),
+        OrderKind::STOP_BUY | OrderKind::STOP_SELL => order.trigger_price,
+        _ => Some(order.price_per),
+    };
+
+    price
+        .map(|x| x.checked_mul(amount).unwrap_or(Price::new(i64::MAX, x.scale())))
+        .unwrap_or_default()
+}
 
-    //
-    let mut to_remove = vec![];
+/// Remember a trade by an order in a group, so `settle_groups` can act on it
+fn note_group_fill(summary: &mut Summary, order: &Order, amount: u32) {
+    if order.oco.is_some() || order.bracket.is_some() {
+        summary.group_fills.push((order.clone(), amount));
+    }
+}
 
-    // high to low
-    for i in (end..buy_orders.len()).rev() {
-        if order.amount < 1 {
+/// Record a trade between an incoming order and the resting order it matched. The resting
+/// order was on the book first, so its price is the one the trade happens at, and its user
+/// pays the maker fee.
+fn trade(order: &Order, resting: &Order, amount: u32, fees: Option<&FeeSchedule>) -> Transaction {
+    let (buyer, seller) = match resting.kind {
+        OrderKind::SELL => (order, resting),
+        _ => (resting, order),
+    };
+
+    let mut transaction = Transaction::new(
+        buyer.user_id.clone(),
+        seller.user_id.clone(),
+        amount,
+        resting.price_per,
+    );
+
+    if let Some(fees) = fees {
+        let value = transaction.value();
+        let (maker, taker) = (fees.maker_fee(value), fees.taker_fee(value));
+        (transaction.buyer_fee, transaction.seller_fee) = match resting.kind {
+            OrderKind::SELL => (taker, maker),
+            _ => (maker, taker),
+        };
+    }
+
+    transaction
+}
+
+/// Match `order` against one side of the book, best price first and oldest first within a
+/// price level, for as long as `crosses` accepts the price of the next level. Resting orders
+/// that have expired by `now` are taken off the book instead of traded with. Resting orders
+/// that will not take the size on offer are passed over and keep their place.
+///
+/// An iceberg whose slice fills shows its next slice from the reserve, taking a new arrival
+/// number from `sequence`. Trades against slices shown during this sweep count as hidden.
+fn sweep(
+    order: &mut Order,
+    side: &mut BookSide,
+    index: &mut Index,
+    sequence: &mut u64,
+    summary: &mut Summary,
+    now: u64,
+    crosses: impl Fn(Price) -> bool,
+) {
+    let mut replenished: HashSet<Uuid> = HashSet::new();
+    let mut after: Option<Price> = None;
+
+    while order.amount > 0 {
+        let (price, queue) = match side.next_level(after) {
+            Some(level) => level,
+            None => break,
+        };
+
+        if !crosses(price) {
             break;
         }
 
-        if buy_orders[i].price_per >= order.price_per {
-            let amount;
-            if buy_orders[i].amount > order.amount {
-                amount = order.amount;
-                buy_orders[i].amount -= order.amount;
-                order.amount = 0;
-            } else if buy_orders[i].amount < order.amount {
-                amount = buy_orders[i].amount;
-                order.amount -= buy_orders[i].amount;
-                buy_orders[i].amount = 0;
-            } else {
-                amount = order.amount;
-                buy_orders[i].amount = 0;
-                order.amount = 0;
+        // Walk the level by slot number, which stays put as orders are taken out
+        let mut slot = queue.slot_range().0;
+        while order.amount > 0 && slot < queue.slot_range().1 {
+            slot = slot.max(queue.slot_range().0);
+            let resting = match queue.slot_mut(slot) {
+                Some(resting) => resting,
+                None => {
+                    slot += 1;
+                    continue;
+                }
+            };
+
+            if resting.is_expired(now) {
+                if let Some(expired) = queue.take(slot) {
+                    index.remove(&expired);
+                    summary.cancelled.push(Cancellation::new(expired, CancelReason::Expired));
+                }
+                continue;
             }
 
-            if buy_orders[i].amount < 1 {
-                to_remove.push(i);
+            if !resting.accepts(order.amount.min(resting.amount)) {
+                slot += 1;
+                continue;
             }
 
-            // Buyer sets price
-            let transaction = Transaction::new(
-                order.user_id.clone(),
-                buy_orders[i].user_id.clone(),
-                amount,
-                buy_orders[i].price_per,
-            );
-            summary.transactions.push(transaction);
-            summary.to_update.push(buy_orders[i].clone());
-        } else {
-            break;
+            if let Some(mode) = self_trade(order, resting) {
+                prevent_self_trade(order, mode, queue, slot, index, summary);
+                continue;
+            }
+
+            let amount = fill(order, resting);
+            index.reduce(&resting.user_id, amount);
+            summary
+                .transactions
+                .push(trade(order, resting, amount, summary.fee_schedule.as_ref()));
+            note_group_fill(summary, order, amount);
+            note_group_fill(summary, resting, amount);
+            if replenished.contains(&resting.id) {
+                summary.hidden_filled += amount;
+            }
+
+            if resting.amount > 0 {
+                summary.to_update.push(resting.clone());
+                continue;
+            }
+
+            if let Some(mut filled) = queue.take(slot) {
+                if filled.reserve > 0 {
+                    // The next slice goes to the back of the level, like a new order
+                    filled.replenish();
+                    filled.seq = *sequence;
+                    *sequence = sequence.saturating_add(1);
+                    replenished.insert(filled.id);
+
+                    summary.to_update.push(filled.clone());
+                    queue.push_back(filled);
+                } else {
+                    index.remove(&filled);
+                    summary.to_update.push(filled);
+                }
+            }
         }
+
+        side.prune(price);
+        after = Some(price);
     }
+}
 
-    to_remove.sort();
-    to_remove.reverse();
-    for i in 0..to_remove.len() {
-        buy_orders.remove(to_remove[i]);
+/// Keep an incoming order from trading with the resting order in `slot`, which belongs
+/// to the same user. An incoming order that is cancelled is left with nothing to match.
+fn prevent_self_trade(
+    order: &mut Order,
+    mode: SelfTrade,
+    queue: &mut Level,
+    slot: u64,
+    index: &mut Index,
+    summary: &mut Summary,
+) {
+    let (cancel_resting, cancel_incoming) = match mode {
+        SelfTrade::CancelNewest => (false, true),
+        SelfTrade::CancelOldest => (true, false),
+        SelfTrade::CancelBoth => (true, true),
+        SelfTrade::DecrementAndCancel => {
+            let resting = match queue.slot_mut(slot) {
+                Some(resting) => resting,
+                None => return,
+            };
+            let amount = order.amount.min(resting.amount + resting.reserve);
+
+            // Like an amendment, the hidden reserve goes first
+            let from_reserve = amount.min(resting.reserve);
+            resting.reserve -= from_reserve;
+            resting.amount -= amount - from_reserve;
+            order.amount -= amount;
+            index.reduce(&resting.user_id, amount);
+
+            if resting.amount > 0 {
+                summary.to_update.push(resting.clone());
+            } else if let Some(mut resting) = queue.take(slot) {
+                index.remove(&resting);
+                resting.amount = amount;
+                summary.cancelled.push(Cancellation::new(resting, CancelReason::SelfTrade));
+            }
+            if order.amount == 0 {
+                let mut cancelled = order.clone();
+                cancelled.amount = amount;
+                summary.cancelled.push(Cancellation::new(cancelled, CancelReason::SelfTrade));
+            }
+            return;
+        }
+    };
+
+    if cancel_resting {
+        if let Some(resting) = queue.take(slot) {
+            index.remove(&resting);
+            summary.cancelled.push(Cancellation::new(resting, CancelReason::SelfTrade));
+        }
     }
+    if cancel_incoming {
+        summary.cancelled.push(Cancellation::new(order.clone(), CancelReason::SelfTrade));
+        order.amount = 0;
+    }
+}
 
-    if order.amount < 1 {
-        return;
+fn buy(order: Order, ledger: &mut Ledger, summary: &mut Summary, now: u64) {
+    let mut order = order;
+    let limit = order.price_per;
+    let crosses = |price: Price| price <= limit;
+
+    // low to high
+    if can_trade(&order, &ledger.sell_side, now, crosses) {
+        sweep(
+            &mut order,
+            &mut ledger.sell_side,
+            &mut ledger.index,
+            &mut ledger.sequence,
+            summary,
+            now,
+            crosses,
+        );
     }
 
-    // Add our buy order to the buy ledger
-    let pos = sell_orders.binary_search(&order).unwrap_or_else(|e| e);
-    sell_orders.insert(pos, order.clone());
-    summary.created = Some(order);
+    rest(order, ledger, summary);
 }
 
-fn market_sell(order: Order, ledger: &mut Ledger, summary: &mut Summary) {
-    let buy_orders: &mut Vec<Order> = &mut ledger.buy_orders;
-
+fn sell(order: Order, ledger: &mut Ledger, summary: &mut Summary, now: u64) {
     let mut order = order;
+    let limit = order.price_per;
+    let crosses = |price: Price| price >= limit;
+
+    // high to low
+    if can_trade(&order, &ledger.buy_side, now, crosses) {
+        sweep(
+            &mut order,
+            &mut ledger.buy_side,
+            &mut ledger.index,
+            &mut ledger.sequence,
+            summary,
+            now,
+            crosses,
+        );
+    }
+
+    rest(order, ledger, summary);
+}
 
-    //
-    let mut to_remove = vec![];
+fn market_sell(order: Order, ledger: &mut Ledger, summary: &mut Summary, now: u64) {
+    let mut order = order;
+    let limit = slippage_limit(&order, &ledger.buy_side, now);
+    let crosses = |price: Price| limit.is_none_or(|limit| price >= limit);
 
     // high to low
-    for i in (0..buy_orders.len()).rev() {
-        if order.amount < 1 {
-            break;
-        }
+    if can_trade(&order, &ledger.buy_side, now, crosses) {
+        sweep(
+            &mut order,
+            &mut ledger.buy_side,
+            &mut ledger.index,
+            &mut ledger.sequence,
+            summary,
+            now,
+            crosses,
+        );
+    }
 
-        let amount;
-        if buy_orders[i].amount > order.amount {
-            amount = order.amount;
-            buy_orders[i].amount -= order.amount;
-            order.amount = 0;
-        } else if buy_orders[i].amount < order.amount {
-            amount = buy_orders[i].amount;
-            order.amount -= buy_orders[i].amount;
-            buy_orders[i].amount = 0;
-        } else {
-            amount = order.amount;
-            buy_orders[i].amount = 0;
-            order.amount = 0;
-        }
+    let stopped = ledger.buy_side.best_price(now).is_some_and(|best| !crosses(best));
+    drop_remainder(order, stopped, summary);
+}
 
-        if buy_orders[i].amount < 1 {
-            to_remove.push(i);
-        }
+fn market_buy(order: Order, ledger: &mut Ledger, summary: &mut Summary, now: u64) {
+    let mut order = order;
+    let limit = slippage_limit(&order, &ledger.sell_side, now);
+    let crosses = |price: Price| limit.is_none_or(|limit| price <= limit);
 
-        let transaction = Transaction::new(
-            order.user_id.clone(),
-            buy_orders[i].user_id.clone(),
-            amount,
-            order.price_per,
+    // low to high
+    if can_trade(&order, &ledger.sell_side, now, crosses) {
+        sweep(
+            &mut order,
+            &mut ledger.sell_side,
+            &mut ledger.index,
+            &mut ledger.sequence,
+            summary,
+            now,
+            crosses,
         );
-        summary.transactions.push(transaction);
-        summary.to_update.push(buy_orders[i].clone());
     }
 
-    to_remove.sort();
-    to_remove.reverse();
-    for i in 0..to_remove.len() {
-        buy_orders.remove(to_remove[i]);
+    let stopped = ledger.sell_side.best_price(now).is_some_and(|best| !crosses(best));
+    drop_remainder(order, stopped, summary);
+}
+
+/// Size a market buy by its budget: as many units as the budget is sure to buy from the
+/// book, taker fees included, no more than its amount if it gave one, and none if that is
+/// less than the item's minimum quantity
+fn size_by_budget(
+    order: &mut Order,
+    budget: Price,
+    ledger: &Ledger,
+    spec: &ItemSpec,
+    fees: Option<&FeeSchedule>,
+    now: u64,
+) {
+    let limit = slippage_limit(order, &ledger.sell_side, now);
+    let crosses = |price: Price| limit.is_none_or(|limit| price <= limit);
+
+    let mut amount = ledger.sell_side.affordable(order, budget, spec.lot_size, fees, now, crosses);
+    if let Some(max) = spec.max_quantity {
+        amount = amount.min(max - max % spec.lot_size.max(1));
     }
+    if order.amount > 0 {
+        amount = amount.min(order.amount);
+    }
+    // Too few to trade is as good as none
+    if amount < spec.min_quantity {
+        amount = 0;
+    }
+    order.amount = amount;
 }
 
-fn market_buy(order: Order, ledger: &mut Ledger, summary: &mut Summary) {
-    let sell_orders: &mut Vec<Order> = &mut ledger.sell_orders;
+/// What a budget buy paid for the trades in a summary, its fees included
+fn spending(summary: &Summary) -> Price {
+    summary
+        .transactions
+        .iter()
+        .filter_map(|x| x.value().checked_add(x.buyer_fee))
+        .fold(Price::default(), |total, cost| total.checked_add(cost).unwrap_or(total))
+}
 
-    let mut order = order;
+/// The worst price a market order will trade at, measured from `side`'s best price when it
+/// arrives. `None` if it will take any price.
+fn slippage_limit(order: &Order, side: &BookSide, now: u64) -> Option<Price> {
+    order.slippage?.limit(side.best_price(now)?, order.kind.is_buy())
+}
 
-    let mut to_remove = vec![];
+/// Market orders never rest: whatever the book could not fill is dropped. `stopped` says
+/// the book had more, but only beyond the order's slippage limit.
+fn drop_remainder(order: Order, stopped: bool, summary: &mut Summary) {
+    if order.amount < 1 {
+        return;
+    }
 
-    // low to high
-    for i in 0..sell_orders.len() {
-        if order.amount < 1 {
-            break;
-        }
+    let reason = match order.time_in_force {
+        TimeInForce::FillOrKill => CancelReason::FillOrKill,
+        _ if stopped => CancelReason::Slippage,
+        _ => CancelReason::NoLiquidity,
+    };
+    summary.cancelled.push(Cancellation::new(order, reason));
+}
 
-        let amount;
-        if sell_orders[i].amount > order.amount {
-            amount = order.amount;
-            sell_orders[i].amount -= order.amount;
-            order.amount = 0;
-        } else if sell_orders[i].amount < order.amount {
-            amount = sell_orders[i].amount;
-            order.amount -= sell_orders[i].amount;
-            sell_orders[i].amount = 0;
-        } else {
-            amount = order.amount;
-            sell_orders[i].amount = 0;
-            order.amount = 0;
+/// Make sure a post-only order will not trade on arrival, repricing it if it asks for that.
+/// Returns false if it has to be cancelled instead.
+fn post(order: &mut Order, post_only: PostOnly, ledger: &Ledger, spec: &ItemSpec, now: u64) -> bool {
+    let tick = spec.tick_size.unwrap_or(Price::new(1, spec.decimals));
+
+    let repriced = match order.kind {
+        OrderKind::BUY => match ledger.sell_side.best_price(now) {
+            Some(best) if best <= order.price_per => best.checked_sub(tick),
+            _ => return true,
+        },
+        _ => match ledger.buy_side.best_price(now) {
+            Some(best) if best >= order.price_per => best.checked_add(tick),
+            _ => return true,
+        },
+    };
+
+    match (post_only, repriced) {
+        (PostOnly::Reprice, Some(price)) => {
+            order.price_per = price;
+            spec.validate(order).is_ok()
         }
+        _ => false,
+    }
+}
 
-        if sell_orders[i].amount < 1 {
-            to_remove.push(i);
-        }
+/// Whether `side` can give an incoming order the least it will trade on arrival. Orders with
+/// no such minimum always go on to match.
+fn can_trade(order: &Order, side: &BookSide, now: u64, crosses: impl Fn(Price) -> bool) -> bool {
+    let least = order.least_fill();
+    least == 0 || side.fillable(order, now, crosses) >= least
+}
 
-        let transaction = Transaction::new(
-            order.user_id.clone(),
-            sell_orders[i].user_id.clone(),
-            amount,
-            sell_orders[i].price_per,
-        );
-        summary.transactions.push(transaction);
-        summary.to_update.push(sell_orders[i].clone());
+/// Put what is left of a limit order on the book, unless it was only good for this moment
+fn rest(order: Order, ledger: &mut Ledger, summary: &mut Summary) {
+    if order.amount < 1 {
+        return;
     }
 
-    to_remove.sort();
-    to_remove.reverse();
-    for i in 0..to_remove.len() {
-        sell_orders.remove(to_remove[i]);
+    match order.time_in_force {
+        TimeInForce::GoodTillCancelled => {
+            let mut order = order;
+            order.hide_reserve();
+            ledger.insert(order.clone());
+            summary.created = Some(order);
+        }
+        TimeInForce::FillOrKill => summary.cancelled.push(Cancellation::new(order, CancelReason::FillOrKill)),
+        TimeInForce::ImmediateOrCancel => summary.cancelled.push(Cancellation::new(order, CancelReason::ImmediateOrCancel)),
     }
 }
@@ -0,0 +1,270 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Most decimal places a price can carry
+pub const MAX_SCALE: u32 = 18;
+
+/// A fixed-point decimal: `units` counted in steps of 10^-scale.
+///
+/// `12.50` is `Price { units: 1250, scale: 2 }`. Prices with different scales compare by
+/// value, so `12.5 == 12.50`. On the wire a price is a decimal string, which is lossless.
+/// The default price is zero.
+#[derive(Clone, Copy, Default)]
+pub struct Price {
+    units: i64,
+    scale: u32,
+}
+
+impl Price {
+    pub fn new(units: i64, scale: u32) -> Price {
+        assert!(scale <= MAX_SCALE, "price scale out of range");
+        Price { units, scale }
+    }
+
+    pub fn units(&self) -> i64 {
+        self.units
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Units expressed at a larger scale
+    fn widen(&self, scale: u32) -> i128 {
+        self.units as i128 * 10i128.pow(scale - self.scale)
+    }
+
+    /// Same value at exactly `scale` decimal places, if no precision is lost
+    pub fn rescale(&self, scale: u32) -> Option<Price> {
+        if scale >= self.scale {
+            let units = i64::try_from(self.widen(scale)).ok()?;
+            return Some(Price::new(units, scale));
+        }
+
+        let divisor = 10i64.pow(self.scale - scale);
+        if self.units % divisor != 0 {
+            return None;
+        }
+        Some(Price::new(self.units / divisor, scale))
+    }
+
+    /// `self + other`, at the larger of the two scales. `None` on overflow.
+    pub fn checked_add(&self, other: Price) -> Option<Price> {
+        let scale = self.scale.max(other.scale);
+        let units = self.widen(scale) + other.widen(scale);
+        Some(Price::new(i64::try_from(units).ok()?, scale))
+    }
+
+    /// `self - other`, at the larger of the two scales. `None` on overflow.
+    pub fn checked_sub(&self, other: Price) -> Option<Price> {
+        let scale = self.scale.max(other.scale);
+        let units = self.widen(scale) - other.widen(scale);
+        Some(Price::new(i64::try_from(units).ok()?, scale))
+    }
+
+    /// `percent` percent of this price, rounded toward zero at this price's scale.
+    /// `None` on overflow.
+    pub fn percent(&self, percent: Price) -> Option<Price> {
+        let divisor = 100 * 10i128.pow(percent.scale);
+        let units = self.units as i128 * percent.units as i128 / divisor;
+        Some(Price::new(i64::try_from(units).ok()?, self.scale))
+    }
+
+    /// `amount` times this price. `None` on overflow.
+    pub fn checked_mul(&self, amount: u32) -> Option<Price> {
+        Some(Price::new(self.units.checked_mul(amount as i64)?, self.scale))
+    }
+
+    /// How many whole `price`s this amount covers. Zero unless both are positive.
+    pub fn fits(&self, price: Price) -> u32 {
+        let scale = self.scale.max(price.scale);
+        let (total, each) = (self.widen(scale), price.widen(scale));
+        if total <= 0 || each <= 0 {
+            return 0;
+        }
+        u32::try_from(total / each).unwrap_or(u32::MAX)
+    }
+
+    /// Whether this price is a whole number of `step`s. Always false for a zero step.
+    pub fn is_multiple_of(&self, step: Price) -> bool {
+        let scale = self.scale.max(step.scale);
+        let step = step.widen(scale);
+        step != 0 && self.widen(scale) % step == 0
+    }
+
+    /// Largest price at `scale` decimal places that is not above this one.
+    /// `None` if the value does not fit at that scale.
+    pub fn floor(&self, scale: u32) -> Option<Price> {
+        if scale >= self.scale {
+            return self.rescale(scale);
+        }
+
+        let divisor = 10i64.pow(self.scale - scale);
+        Some(Price::new(self.units.div_euclid(divisor), scale))
+    }
+
+    /// Smallest price at `scale` decimal places that is not below this one.
+    /// `None` if the value does not fit at that scale.
+    pub fn ceil(&self, scale: u32) -> Option<Price> {
+        let floor = self.floor(scale)?;
+        if floor == *self {
+            return Some(floor);
+        }
+        Some(Price::new(floor.units.checked_add(1)?, scale))
+    }
+}
+
+impl From<i64> for Price {
+    fn from(units: i64) -> Price {
+        Price::new(units, 0)
+    }
+}
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Price {}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        self.widen(scale).cmp(&other.widen(scale))
+    }
+}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for Price {
+    // Hash the value with trailing zeros stripped so equal prices hash alike
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut units = self.units;
+        let mut scale = self.scale;
+        while scale > 0 && units % 10 == 0 {
+            units /= 10;
+            scale -= 1;
+        }
+        units.hash(state);
+        scale.hash(state);
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.units < 0 { "-" } else { "" };
+        let units = self.units.unsigned_abs();
+
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, units);
+        }
+
+        let divisor = 10u64.pow(self.scale);
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            units / divisor,
+            units % divisor,
+            width = self.scale as usize
+        )
+    }
+}
+
+impl fmt::Debug for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParsePriceError;
+
+impl fmt::Display for ParsePriceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal price")
+    }
+}
+
+impl FromStr for Price {
+    type Err = ParsePriceError;
+
+    fn from_str(s: &str) -> Result<Price, ParsePriceError> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (digits, ""),
+        };
+
+        let scale = fraction.len() as u32;
+        if whole.is_empty() || scale > MAX_SCALE {
+            return Err(ParsePriceError);
+        }
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(ParsePriceError);
+        }
+
+        let units: i64 = format!("{}{}", whole, fraction)
+            .parse()
+            .map_err(|_| ParsePriceError)?;
+
+        Ok(Price::new(if negative { -units } else { units }, scale))
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Price, D::Error> {
+        deserializer.deserialize_any(PriceVisitor)
+    }
+}
+
+/// Accepts decimal strings as well as plain JSON numbers from older clients
+struct PriceVisitor;
+
+impl<'de> Visitor<'de> for PriceVisitor {
+    type Value = Price;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a decimal string or number")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Price, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Price, E> {
+        Ok(Price::from(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Price, E> {
+        i64::try_from(value).map(Price::from).map_err(E::custom)
+    }
+
+    // Floats are read back through their shortest decimal form, so 0.1 stays 0.1
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Price, E> {
+        if !value.is_finite() {
+            return Err(E::custom(ParsePriceError));
+        }
+        self.visit_str(&value.to_string())
+    }
+}
@@ -1,17 +1,146 @@
 use std::cmp::Ordering;
 use uuid::Uuid;
-use ordered_float::OrderedFloat; // 1.0.2
+
+use crate::error::MarketError;
+use crate::price::{Price, MAX_SCALE};
 
 use serde::{Serialize, Deserialize};
-use wasm_bindgen::prelude::*;
 
 
+#[allow(non_camel_case_types)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum OrderKind {
     BUY,
     SELL,
     MARKET_BUY,
     MARKET_SELL,
+    /// Market buy once the price rises to the trigger
+    STOP_BUY,
+    /// Market sell once the price falls to the trigger
+    STOP_SELL,
+    /// Limit buy once the price rises to the trigger
+    STOP_LIMIT_BUY,
+    /// Limit sell once the price falls to the trigger
+    STOP_LIMIT_SELL,
+}
+
+impl OrderKind {
+    pub fn is_buy(&self) -> bool {
+        matches!(self, OrderKind::BUY | OrderKind::MARKET_BUY | OrderKind::STOP_BUY | OrderKind::STOP_LIMIT_BUY)
+    }
+
+    /// Whether orders of this kind carry a limit price
+    pub fn is_limit(&self) -> bool {
+        matches!(self, OrderKind::BUY | OrderKind::SELL | OrderKind::STOP_LIMIT_BUY | OrderKind::STOP_LIMIT_SELL)
+    }
+
+    /// Whether orders of this kind wait for a trigger price
+    pub fn is_stop(&self) -> bool {
+        matches!(self, OrderKind::STOP_BUY | OrderKind::STOP_SELL | OrderKind::STOP_LIMIT_BUY | OrderKind::STOP_LIMIT_SELL)
+    }
+}
+
+/// How long an order may wait on the book for the rest of its amount
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum TimeInForce {
+    /// Rests until filled or cancelled
+    #[default]
+    #[serde(rename = "GTC")]
+    GoodTillCancelled,
+    /// Trades what it can at once and cancels the rest
+    #[serde(rename = "IOC")]
+    ImmediateOrCancel,
+    /// Trades its whole amount at once or does nothing
+    #[serde(rename = "FOK")]
+    FillOrKill,
+}
+
+/// What to do with a post-only order that would trade on arrival
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PostOnly {
+    /// Cancel it
+    Reject,
+    /// Move its price one tick short of the opposite side's best price
+    Reprice,
+}
+
+/// How far a trailing stop's trigger follows behind the last traded price
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Trail {
+    /// A fixed distance
+    Offset(Price),
+    /// A percentage of the price
+    Percent(Price),
+}
+
+impl Trail {
+    /// Trigger price this far from `price`: above it for a buy stop, below it for a sell stop
+    pub fn trigger(&self, price: Price, buy: bool) -> Option<Price> {
+        let distance = match self {
+            Trail::Offset(offset) => *offset,
+            Trail::Percent(percent) => price.percent(*percent)?,
+        };
+
+        if buy {
+            price.checked_add(distance)
+        } else {
+            price.checked_sub(distance)
+        }
+    }
+}
+
+/// The worst price a market order will trade at, so a thin book cannot run it far from the best price
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Slippage {
+    /// A fixed distance from the best price when it arrives
+    Offset(Price),
+    /// A percentage of the best price when it arrives
+    Percent(Price),
+    /// A set price
+    WorstPrice(Price),
+}
+
+impl Slippage {
+    /// Worst acceptable price given the opposite side's `best`: above it for a buy, below it for a sell
+    pub fn limit(&self, best: Price, buy: bool) -> Option<Price> {
+        let distance = match self {
+            Slippage::Offset(offset) => *offset,
+            Slippage::Percent(percent) => best.percent(*percent)?,
+            Slippage::WorstPrice(price) => return Some(*price),
+        };
+
+        if buy {
+            best.checked_add(distance)
+        } else {
+            best.checked_sub(distance)
+        }
+    }
+}
+
+/// What happens when an order would trade with another order of the same user
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SelfTrade {
+    /// Cancel the incoming order
+    CancelNewest,
+    /// Cancel the resting order and go on matching
+    CancelOldest,
+    /// Cancel both
+    CancelBoth,
+    /// Take the smaller amount off both, cancelling whichever runs out
+    DecrementAndCancel,
+}
+
+/// The exits a bracket order places once its entry trades, as a one-cancels-other pair
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Bracket {
+    /// Limit price to close out at a profit
+    pub take_profit: Price,
+    /// Trigger price to close out at a loss
+    pub stop_loss: Price,
 }
 
 #[derive(Debug, Clone)]
@@ -20,17 +149,143 @@ pub struct Order {
     pub user_id: String,
     pub kind: OrderKind,
     pub amount: u32,
-    pub price_per: OrderedFloat<f32>
+    pub price_per: Price,
+    /// Arrival sequence number, assigned by the ledger. Breaks ties between orders at the same price.
+    pub seq: u64,
+    pub time_in_force: TimeInForce,
+    /// Market time at which the order leaves the book, if it is not filled first
+    pub expires_at: Option<u64>,
+    /// Set on orders that must only ever add liquidity
+    pub post_only: Option<PostOnly>,
+    /// Last traded price at which a stop order activates
+    pub trigger_price: Option<Price>,
+    /// Makes a stop order trailing: its trigger follows the last traded price
+    pub trail: Option<Trail>,
+    /// Makes a limit order an iceberg: only this much of it is shown on the book at once
+    pub display: Option<u32>,
+    /// The part of an iceberg not on show. `amount` is only the visible slice.
+    pub reserve: u32,
+    /// Only trade the whole of what is left in one go
+    pub all_or_none: bool,
+    /// The least the order will trade: in total on arrival, then in any one trade while it rests
+    pub min_fill: Option<u32>,
+    /// The other leg of a one-cancels-other pair: trading or leaving the book cancels it
+    pub oco: Option<Uuid>,
+    /// Exits to place for whatever this order trades
+    pub bracket: Option<Bracket>,
+    /// Bounds how far a market order, or a stop order once triggered, may sweep the book
+    pub slippage: Option<Slippage>,
+    /// Makes a market buy spend up to this much, buying as many units as it covers
+    pub budget: Option<Price>,
+    /// Stops the order trading with its own user's orders
+    pub self_trade: Option<SelfTrade>,
 }
 
 impl Order {
-    fn new(user_id: String, kind: OrderKind, amount: u32, price_per: f32) -> Order {
+    fn new(user_id: String, kind: OrderKind, amount: u32, price_per: Price) -> Order {
         Order {
             id: Uuid::new_v4(), 
             user_id: user_id,
             kind: kind,
             amount: amount,
-            price_per: OrderedFloat(price_per),
+            price_per: price_per,
+            seq: 0,
+            time_in_force: TimeInForce::default(),
+            expires_at: None,
+            post_only: None,
+            trigger_price: None,
+            trail: None,
+            display: None,
+            reserve: 0,
+            all_or_none: false,
+            min_fill: None,
+            oco: None,
+            bracket: None,
+            slippage: None,
+            budget: None,
+            self_trade: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Whether this order, resting on the book, will take a trade of `amount` in one go.
+    /// A minimum larger than what is left only asks for what is left.
+    pub fn accepts(&self, amount: u32) -> bool {
+        if self.all_or_none {
+            return amount >= self.amount + self.reserve;
+        }
+
+        amount >= self.min_fill.unwrap_or(0).min(self.amount)
+    }
+
+    /// The least this order will trade on arrival if it is to trade at all
+    pub fn least_fill(&self) -> u32 {
+        if self.all_or_none || self.time_in_force == TimeInForce::FillOrKill {
+            return self.amount;
+        }
+
+        self.min_fill.unwrap_or(0).min(self.amount)
+    }
+
+    /// The take-profit and stop-loss exits of a bracket order, sized for `amount` of it,
+    /// linked to each other
+    pub fn exits(&self, amount: u32) -> Option<(Order, Order)> {
+        let bracket = self.bracket?;
+        let (limit, stop) = match self.kind.is_buy() {
+            true => (OrderKind::SELL, OrderKind::STOP_SELL),
+            false => (OrderKind::BUY, OrderKind::STOP_BUY),
+        };
+
+        let mut take_profit = Order::new(self.user_id.clone(), limit, amount, bracket.take_profit);
+        let mut stop_loss = Order::new(self.user_id.clone(), stop, amount, Price::default());
+        stop_loss.trigger_price = Some(bracket.stop_loss);
+        take_profit.oco = Some(stop_loss.id);
+        stop_loss.oco = Some(take_profit.id);
+
+        Some((take_profit, stop_loss))
+    }
+
+    /// Put all but a display-sized slice of an iceberg into its reserve
+    pub fn hide_reserve(&mut self) {
+        if let Some(display) = self.display {
+            let total = self.amount + self.reserve;
+            self.amount = total.min(display);
+            self.reserve = total - self.amount;
+        }
+    }
+
+    /// Show an iceberg's next slice once the visible one has filled
+    pub fn replenish(&mut self) {
+        let slice = self.reserve.min(self.display.unwrap_or(self.reserve));
+        self.amount += slice;
+        self.reserve -= slice;
+    }
+
+    /// The order as other traders may see it, without an iceberg's reserve or the groups
+    /// and settings only its owner should know about
+    pub fn to_public_json(&self) -> OrderJSON {
+        let mut json = self.to_json();
+        json.display = None;
+        json.reserve = 0;
+        json.oco = None;
+        json.bracket = None;
+        json.self_trade = None;
+        json
+    }
+
+    /// Where a trailing stop's trigger moves to after a trade at `last`. Triggers only ever
+    /// move toward the price, so this is `None` unless the new trigger is tighter.
+    pub fn trailed_trigger(&self, last: Price) -> Option<Price> {
+        let buy = self.kind.is_buy();
+        let trigger = self.trail?.trigger(last, buy)?;
+
+        match self.trigger_price {
+            Some(current) if buy && trigger >= current => None,
+            Some(current) if !buy && trigger <= current => None,
+            _ => Some(trigger),
         }
     }
 
@@ -40,11 +295,31 @@ impl Order {
             user_id: self.user_id.clone(), 
             kind: self.kind, 
             amount: self.amount, 
-            price_per: self.price_per.0 
+            price_per: self.price_per,
+            seq: Some(self.seq),
+            time_in_force: self.time_in_force,
+            expires_at: self.expires_at,
+            post_only: self.post_only,
+            trigger_price: self.trigger_price,
+            trail: self.trail,
+            display: self.display,
+            reserve: self.reserve,
+            all_or_none: self.all_or_none,
+            min_fill: self.min_fill,
+            oco: self.oco.map(|x| x.to_string()),
+            bracket: self.bracket,
+            slippage: self.slippage,
+            budget: self.budget,
+            self_trade: self.self_trade,
         }
     }
 
     pub fn from_json(json: OrderJSON) -> Option<Order> {
+        let oco = match json.oco {
+            Some(oco) => Some(Uuid::try_parse(oco.as_str()).ok()?),
+            None => None,
+        };
+
         // Safely parse UUID
         match Uuid::try_parse(json.id.as_str()) {
             Ok(id) => Some(Order{
@@ -52,14 +327,30 @@ impl Order {
                 user_id: json.user_id, 
                 kind: json.kind, 
                 amount: json.amount, 
-                price_per: OrderedFloat(json.price_per)
+                price_per: json.price_per,
+                seq: json.seq.unwrap_or(0),
+                time_in_force: json.time_in_force,
+                expires_at: json.expires_at,
+                post_only: json.post_only,
+                trigger_price: json.trigger_price,
+                trail: json.trail,
+                display: json.display,
+                reserve: json.reserve,
+                all_or_none: json.all_or_none,
+                min_fill: json.min_fill,
+                oco,
+                bracket: json.bracket,
+                slippage: json.slippage,
+                budget: json.budget,
+                self_trade: json.self_trade,
             }),
             Err(_) => None
         }
     }
 
+    /// `None` if the JSON is malformed or the id is not a UUID
     pub fn from_json_string(json_str: &str) -> Option<Order> {
-        let data: OrderJSON = serde_json::from_str(&json_str).unwrap_throw();
+        let data: OrderJSON = serde_json::from_str(json_str).ok()?;
         Order::from_json(data)
     }
 }
@@ -70,7 +361,42 @@ pub struct OrderJSON {
     pub user_id: String,
     pub kind: OrderKind,
     pub amount: u32,
-    pub price_per: f32
+    pub price_per: Price,
+    #[serde(default)]
+    pub seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub time_in_force: TimeInForce,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub post_only: Option<PostOnly>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub trigger_price: Option<Price>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub trail: Option<Trail>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub display: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub reserve: u32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub all_or_none: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub min_fill: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub oco: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub bracket: Option<Bracket>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub slippage: Option<Slippage>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub budget: Option<Price>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub self_trade: Option<SelfTrade>,
+}
+
+/// Lets optional order fields stay out of the JSON until they are used
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 impl PartialEq for Order {
@@ -81,34 +407,309 @@ impl PartialEq for Order {
 
 impl Eq for Order {}
 
+/// Price-time priority: orders compare by price, then by arrival.
 impl Ord for Order {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.price_per.cmp(&other.price_per)
+        self.price_per
+            .cmp(&other.price_per)
+            .then(self.seq.cmp(&other.seq))
     }
 }
 
 impl PartialOrd for Order {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.price_per.partial_cmp(&other.price_per)
+        Some(self.cmp(other))
+    }
+}
+
+/// Trading rules for one item. Orders breaking them are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ItemSpec {
+    /// Decimal places prices are quoted in
+    pub decimals: u32,
+    /// Limit prices must be a whole number of ticks. Without one, prices are rounded to `decimals`.
+    pub tick_size: Option<Price>,
+    /// Amounts must be a whole number of lots
+    pub lot_size: u32,
+    pub min_quantity: u32,
+    pub max_quantity: Option<u32>,
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+    /// Fees for this item's trades, in place of the market's
+    pub fees: Option<FeeSchedule>,
+}
+
+impl Default for ItemSpec {
+    fn default() -> ItemSpec {
+        ItemSpec {
+            decimals: 2,
+            tick_size: None,
+            lot_size: 1,
+            min_quantity: 1,
+            max_quantity: None,
+            min_price: None,
+            max_price: None,
+            fees: None,
+        }
     }
 }
 
+impl ItemSpec {
+    /// Check an order against the spec
+    pub fn validate(&self, order: &Order) -> Result<(), MarketError> {
+        // A budget buy without an amount is sized by the book when it arrives
+        if order.budget.is_none() || order.amount > 0 {
+            self.validate_amount(order.amount)?;
+        }
+        self.validate_terms(order)
+    }
+
+    fn validate_amount(&self, amount: u32) -> Result<(), MarketError> {
+        let min_quantity = self.min_quantity.max(1);
+        if amount < min_quantity {
+            return Err(MarketError::InvalidQuantity(format!("Amount must be at least {}", min_quantity)));
+        }
+
+        if let Some(max) = self.max_quantity {
+            if amount > max {
+                return Err(MarketError::InvalidQuantity(format!("Amount must be at most {}", max)));
+            }
+        }
+
+        if self.lot_size > 1 && !amount.is_multiple_of(self.lot_size) {
+            return Err(MarketError::InvalidQuantity(format!("Amount must be a multiple of the lot size {}", self.lot_size)));
+        }
+
+        Ok(())
+    }
+
+    /// Check everything about an order but its amount
+    fn validate_terms(&self, order: &Order) -> Result<(), MarketError> {
+        if let Some(display) = order.display {
+            if display < 1 {
+                return Err(MarketError::InvalidQuantity("Display quantity must be at least 1".to_string()));
+            }
+            if self.lot_size > 1 && !display.is_multiple_of(self.lot_size) {
+                return Err(MarketError::InvalidQuantity(format!("Display quantity must be a multiple of the lot size {}", self.lot_size)));
+            }
+        }
+
+        if order.min_fill == Some(0) {
+            return Err(MarketError::InvalidQuantity("Minimum fill must be at least 1".to_string()));
+        }
+
+        if let Some(trigger) = order.trigger_price {
+            if trigger <= Price::from(0) {
+                return Err(MarketError::InvalidPrice("Trigger price must be positive".to_string()));
+            }
+            self.validate_price(trigger, "Trigger price")?;
+        }
+
+        match order.trail {
+            Some(Trail::Offset(offset)) if offset <= Price::from(0) => {
+                return Err(MarketError::InvalidPrice("Trailing offset must be positive".to_string()));
+            }
+            Some(Trail::Percent(percent)) if percent <= Price::from(0) || percent >= Price::from(100) => {
+                return Err(MarketError::InvalidPrice("Trailing percentage must be between 0 and 100".to_string()));
+            }
+            _ => {}
+        }
+
+        match order.slippage {
+            Some(Slippage::Offset(price)) | Some(Slippage::Percent(price)) | Some(Slippage::WorstPrice(price))
+                if price <= Price::from(0) =>
+            {
+                return Err(MarketError::InvalidPrice("Slippage limit must be positive".to_string()));
+            }
+            _ => {}
+        }
+
+        // Market orders take whatever price the book offers
+        if !order.kind.is_limit() {
+            return Ok(());
+        }
+
+        if order.price_per <= Price::from(0) {
+            return Err(MarketError::InvalidPrice("Price must be positive".to_string()));
+        }
+
+        self.validate_price(order.price_per, "Price")
+    }
+
+    /// Check a price, named `name` in errors, against the tick size and price bounds
+    fn validate_price(&self, price: Price, name: &str) -> Result<(), MarketError> {
+        if let Some(tick) = self.tick_size {
+            if !price.is_multiple_of(tick) {
+                return Err(MarketError::InvalidPrice(format!("{} must be a multiple of the tick size {}", name, tick)));
+            }
+        }
+
+        if let Some(min) = self.min_price {
+            if price < min {
+                return Err(MarketError::InvalidPrice(format!("{} must be at least {}", name, min)));
+            }
+        }
+
+        if let Some(max) = self.max_price {
+            if price > max {
+                return Err(MarketError::InvalidPrice(format!("{} must be at most {}", name, max)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the spec itself can be applied
+    pub fn check(&self) -> Result<(), MarketError> {
+        if self.decimals > MAX_SCALE {
+            return Err(MarketError::InvalidSpec(format!("Decimals must be at most {}", MAX_SCALE)));
+        }
+
+        if let Some(tick) = self.tick_size {
+            if tick <= Price::from(0) {
+                return Err(MarketError::InvalidSpec("Tick size must be positive".to_string()));
+            }
+        }
+
+        if let Some(fees) = self.fees {
+            fees.check()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Basis points in a whole: 100 basis points are 1%
+const BPS: u32 = 10_000;
+
+/// Fees charged on each trade, in basis points of its value. The resting order's user is the
+/// maker and the incoming order's the taker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeSchedule {
+    pub maker_bps: u32,
+    pub taker_bps: u32,
+    /// Least fee a side pays on a trade, unless the trade is worth less
+    pub min_fee: Option<Price>,
+    /// Paid to the maker instead of charging it `maker_bps`. Paid for out of the taker's fee,
+    /// so cannot be more than it.
+    pub maker_rebate_bps: Option<u32>,
+}
+
+impl FeeSchedule {
+    pub fn check(&self) -> Result<(), MarketError> {
+        if self.maker_bps > BPS || self.taker_bps > BPS {
+            return Err(MarketError::InvalidSpec("Fees must be at most 10000 basis points".to_string()));
+        }
+
+        if self.min_fee.is_some_and(|fee| fee < Price::from(0)) {
+            return Err(MarketError::InvalidSpec("Minimum fee cannot be negative".to_string()));
+        }
+
+        if self.maker_rebate_bps.is_some_and(|rebate| rebate > self.taker_bps) {
+            return Err(MarketError::InvalidSpec("Maker rebate cannot be more than the taker fee".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// What the maker pays on a trade worth `value`. Negative for a rebate.
+    pub fn maker_fee(&self, value: Price) -> Price {
+        match self.maker_rebate_bps {
+            Some(rebate) => Price::default().checked_sub(share(value, rebate)).unwrap_or_default(),
+            None => self.charge(value, self.maker_bps),
+        }
+    }
+
+    /// What the taker pays on a trade worth `value`
+    pub fn taker_fee(&self, value: Price) -> Price {
+        self.charge(value, self.taker_bps)
+    }
+
+    /// The most either side can pay in fees on `trades` trades worth `value` in all
+    pub fn most(&self, value: Price, trades: u32) -> Price {
+        let maker_bps = if self.maker_rebate_bps.is_some() { 0 } else { self.maker_bps };
+        self.bound(value, trades, self.taker_bps.max(maker_bps))
+    }
+
+    /// The most the taker can pay in fees on `trades` trades worth `value` in all
+    pub fn most_as_taker(&self, value: Price, trades: u32) -> Price {
+        self.bound(value, trades, self.taker_bps)
+    }
+
+    // Each trade's fee is its share or the minimum, whichever is more, and never more than
+    // the trade is worth
+    fn bound(&self, value: Price, trades: u32, bps: u32) -> Price {
+        let minimums = self.min_fee.unwrap_or_default().checked_mul(trades).unwrap_or(value);
+        let fee = share(value, bps).checked_add(minimums).unwrap_or(value).min(value);
+        fee.ceil(value.scale()).unwrap_or(fee)
+    }
+
+    fn charge(&self, value: Price, bps: u32) -> Price {
+        let fee = share(value, bps).max(self.min_fee.unwrap_or_default()).min(value);
+        fee.floor(value.scale()).unwrap_or(fee)
+    }
+}
+
+/// Caps on what one user can have open, checked as each order is placed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskLimits {
+    /// Orders resting or waiting to trigger, across every item
+    pub max_open_orders: Option<u32>,
+    /// Most a single order can be worth
+    pub max_notional: Option<Price>,
+    /// Most of one item in open orders, buying and selling together
+    pub max_open_quantity: Option<u32>,
+}
+
+impl RiskLimits {
+    pub fn check(&self) -> Result<(), MarketError> {
+        if self.max_notional.is_some_and(|max| max <= Price::from(0)) {
+            return Err(MarketError::InvalidSpec("Notional limit must be positive".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// `bps` basis points of `value`, rounded toward zero
+fn share(value: Price, bps: u32) -> Price {
+    value.percent(Price::new(bps as i64, 2)).unwrap_or_default()
+}
+
 pub struct OrderRequest {
     pub item: String,
     pub order: Order,
 }
 
 impl OrderRequest {
-    pub fn new(user_id: String, item: String, kind: OrderKind, amount: u32, price_per: f32) -> OrderRequest {
+    pub fn new(user_id: String, item: String, kind: OrderKind, amount: u32, price_per: Price) -> OrderRequest {
         OrderRequest { 
             item: item.to_uppercase(), 
             order: Order::new(user_id, kind, amount, price_per), 
         }
     }
 
-    pub fn from_json_string(json_str: &str) -> OrderRequest {
-        let data: OrderRequestJSON = serde_json::from_str(&json_str).unwrap_throw();
-        OrderRequest::new(data.user_id, data.item, OrderKind::BUY, data.amount, data.price_per)
+    /// `None` if the JSON is malformed
+    pub fn from_json_string(json_str: &str) -> Option<OrderRequest> {
+        let data: OrderRequestJSON = serde_json::from_str(json_str).ok()?;
+        let kind = data.kind.unwrap_or(OrderKind::BUY);
+        let mut order_request = OrderRequest::new(data.user_id, data.item, kind, data.amount, data.price_per);
+        order_request.order.time_in_force = data.time_in_force;
+        order_request.order.expires_at = data.expires_at;
+        order_request.order.post_only = data.post_only;
+        order_request.order.trigger_price = data.trigger_price;
+        order_request.order.trail = data.trail;
+        order_request.order.display = data.display;
+        order_request.order.all_or_none = data.all_or_none;
+        order_request.order.min_fill = data.min_fill;
+        order_request.order.bracket = data.bracket;
+        order_request.order.slippage = data.slippage;
+        order_request.order.budget = data.budget;
+        order_request.order.self_trade = data.self_trade;
+        Some(order_request)
     }
  }
 
@@ -116,8 +717,39 @@ impl OrderRequest {
 struct OrderRequestJSON {
     pub user_id: String, 
     pub item: String, 
+    /// Defaults to a limit BUY
+    #[serde(default)]
+    pub kind: Option<OrderKind>,
+    /// Not needed for market buys with a budget
+    #[serde(default)]
     pub amount: u32, 
-    pub price_per: f32
+    /// Not needed for market orders
+    #[serde(default)]
+    pub price_per: Price,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub post_only: Option<PostOnly>,
+    #[serde(default)]
+    pub trigger_price: Option<Price>,
+    #[serde(default)]
+    pub trail: Option<Trail>,
+    #[serde(default)]
+    pub display: Option<u32>,
+    #[serde(default)]
+    pub all_or_none: bool,
+    #[serde(default)]
+    pub min_fill: Option<u32>,
+    #[serde(default)]
+    pub bracket: Option<Bracket>,
+    #[serde(default)]
+    pub slippage: Option<Slippage>,
+    #[serde(default)]
+    pub budget: Option<Price>,
+    #[serde(default)]
+    pub self_trade: Option<SelfTrade>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -125,18 +757,80 @@ pub struct Transaction {
     pub buyer: String,
     pub seller: String,
     pub amount: u32,
-    pub price_per: f32,
+    pub price_per: Price,
+    /// Fees each side paid on the trade. A negative fee is a rebate.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub buyer_fee: Price,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub seller_fee: Price,
 }
 
 impl Transaction {
-    pub fn new(buyer_id: String, seller_id: String, amount: u32, price_per: OrderedFloat<f32>) -> Transaction {
+    pub fn new(buyer_id: String, seller_id: String, amount: u32, price_per: Price) -> Transaction {
         Transaction {
             buyer: buyer_id,
             seller: seller_id,
             amount: amount,
-            price_per: price_per.0,
+            price_per: price_per,
+            buyer_fee: Price::default(),
+            seller_fee: Price::default(),
         }
     }
+
+    /// What the trade is worth before fees
+    pub fn value(&self) -> Price {
+        self.price_per.checked_mul(self.amount).unwrap_or_default()
+    }
+}
+
+/// Why an order, or what was left of it, was taken out of the market
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CancelReason {
+    /// Nothing left on the book to trade against
+    NoLiquidity,
+    /// The rest of an immediate-or-cancel order
+    ImmediateOrCancel,
+    /// A fill-or-kill order that could not trade in full
+    FillOrKill,
+    /// A resting order whose time ran out
+    Expired,
+    /// The other leg of its one-cancels-other pair traded or left the book
+    Linked,
+    /// The rest of a market order the book could only fill beyond its slippage limit
+    Slippage,
+    /// Would have traded with another order of the same user
+    SelfTrade,
+    /// A post-only order that would have traded on arrival
+    PostOnly,
+    /// A market buy whose budget would not pay for a single lot
+    Budget,
+}
+
+/// An order that will not rest on the book, with the amount that went unfilled
+#[derive(Debug, PartialEq)]
+pub struct Cancellation {
+    pub order: Order,
+    pub reason: CancelReason,
+}
+
+impl Cancellation {
+    pub fn new(order: Order, reason: CancelReason) -> Cancellation {
+        Cancellation { order, reason }
+    }
+
+    pub fn to_json(&self) -> CancellationJSON {
+        CancellationJSON {
+            order: self.order.to_json(),
+            reason: self.reason,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CancellationJSON {
+    pub order: OrderJSON,
+    pub reason: CancelReason,
 }
 
 /// Summary of what occured
@@ -145,7 +839,27 @@ pub struct Summary {
     pub key: String,
     pub transactions: Vec<Transaction>,
     pub to_update: Vec<Order>,
-    pub created: Option<Order>
+    pub created: Option<Order>,
+    /// Orders taken out of the market, such as an unfilled market order or an expired
+    /// resting order found while matching
+    pub cancelled: Vec<Cancellation>,
+    /// Stop orders set off by this order's trades, as they were waiting. What they then
+    /// traded is in `transactions`, and any that went on to rest are in `to_update`.
+    pub triggered: Vec<Order>,
+    /// How much of the amount traded came from iceberg slices that were not on show
+    /// when the order arrived
+    pub hidden_filled: u32,
+    /// Orders placed along with this one as part of its group: the second leg of a
+    /// one-cancels-other pair, or the exits of a bracket whose entry traded. What they then
+    /// traded is in `transactions`, and any that went on to rest are in `to_update`.
+    pub linked: Vec<Order>,
+    /// What a market buy with a budget spent, and what it has left
+    pub spent: Option<Price>,
+    pub budget_left: Option<Price>,
+    /// Fees for trades made while matching
+    pub(crate) fee_schedule: Option<FeeSchedule>,
+    /// Orders in a group that traded, and how much, waiting for the group to be settled
+    pub(crate) group_fills: Vec<(Order, u32)>,
 }
 
 impl Summary {
@@ -154,13 +868,66 @@ impl Summary {
             key: key,
             transactions: vec![],
             to_update: vec![],
-            created: None
+            created: None,
+            cancelled: vec![],
+            triggered: vec![],
+            hidden_filled: 0,
+            linked: vec![],
+            spent: None,
+            budget_left: None,
+            fee_schedule: None,
+            group_fills: vec![],
+        }
+    }
+
+    /// Total amount traded
+    pub fn filled(&self) -> u32 {
+        self.transactions.iter().map(|x| x.amount).sum()
+    }
+
+    /// Total fees charged on the trades
+    pub fn fees(&self) -> Price {
+        self.fee_totals().0
+    }
+
+    /// Total rebates paid on the trades
+    pub fn rebates(&self) -> Price {
+        self.fee_totals().1
+    }
+
+    fn fee_totals(&self) -> (Price, Price) {
+        let mut fees = Price::default();
+        let mut rebates = Price::default();
+        for fee in self.transactions.iter().flat_map(|x| [x.buyer_fee, x.seller_fee]) {
+            if fee > Price::default() {
+                fees = fees.checked_add(fee).unwrap_or(fees);
+            } else {
+                rebates = rebates.checked_sub(fee).unwrap_or(rebates);
+            }
         }
+        (fees, rebates)
     }
 
-    pub fn to_json_str(self) -> String {
+    /// Amount traded against what the book was showing
+    pub fn visible_filled(&self) -> u32 {
+        self.filled() - self.hidden_filled
+    }
 
-        let summary_json = SummaryJSON {
+    /// Add what a follow-on placement did. Its `created` is reported in `to_update`.
+    pub fn absorb(&mut self, other: Summary) {
+        self.transactions.extend(other.transactions);
+        self.to_update.extend(other.to_update);
+        self.to_update.extend(other.created);
+        self.cancelled.extend(other.cancelled);
+        self.triggered.extend(other.triggered);
+        self.hidden_filled += other.hidden_filled;
+        self.linked.extend(other.linked);
+    }
+
+    pub fn to_json(self) -> SummaryJSON {
+        let (fees, rebates) = self.fee_totals();
+        SummaryJSON {
+            status: "SUCCESS".to_string(),
             key: self.key,
             transactions: self.transactions,
             to_update: self.to_update.iter().map(|x| { x.to_json() }).collect(),
@@ -168,16 +935,39 @@ impl Summary {
                 Some(order) => Some(order.to_json()),
                 None => None,
             },
-        };
-
-        serde_json::to_string(&summary_json).unwrap()
+            cancelled: self.cancelled.iter().map(|x| x.to_json()).collect(),
+            triggered: self.triggered.iter().map(|x| x.to_json()).collect(),
+            hidden_filled: self.hidden_filled,
+            linked: self.linked.iter().map(|x| x.to_json()).collect(),
+            spent: self.spent,
+            budget_left: self.budget_left,
+            fees,
+            rebates,
+        }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SummaryJSON {
+    pub status: String,
     pub key: String,
     pub transactions: Vec<Transaction>,
     pub to_update: Vec<OrderJSON>,
-    pub created: Option<OrderJSON>
+    pub created: Option<OrderJSON>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cancelled: Vec<CancellationJSON>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggered: Vec<OrderJSON>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub hidden_filled: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked: Vec<OrderJSON>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spent: Option<Price>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_left: Option<Price>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub fees: Price,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rebates: Price,
 }
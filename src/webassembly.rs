@@ -1,11 +1,20 @@
 use wasm_bindgen::prelude::*;
 use std::collections::HashMap;
 
+pub mod account;
+pub mod book;
+pub mod error;
 pub mod market;
+pub mod price;
 pub mod structs;
 
+use crate::account::Account;
+use crate::error::MarketError;
 use crate::market::{Market, Ledger, LedgerJSON};
-use crate::structs::{OrderRequest, OrderKind, Order};
+use crate::price::Price;
+use crate::structs::{FeeSchedule, ItemSpec, OrderRequest, OrderKind, Order, OrderJSON, RiskLimits, SelfTrade, Summary};
+use serde::Serialize;
+use uuid::Uuid;
 
 
 #[wasm_bindgen]
@@ -35,57 +44,250 @@ impl MarketWrapper {
         order_request_str.to_string()
     }
 
+    /// Set an item's trading rules from a JSON `ItemSpec`. Missing fields take their defaults.
+    pub fn register_item(&mut self, item: String, spec_str: &str) -> String {
+        match serde_json::from_str::<ItemSpec>(spec_str) {
+            Ok(spec) => match self.market.register_item(item, spec) {
+                Ok(()) => success(None),
+                Err(error) => failure(error),
+            },
+            Err(_) => failure("Invalid item spec")
+        }
+    }
+
+    /// Place an order of the request's `kind`, a limit BUY if it has none
+    pub fn place_order(&mut self, order_request_str: &str) -> String {
+        match OrderRequest::from_json_string(order_request_str) {
+            Some(order_request) => summary_status(self.market.place_order(order_request)),
+            None => failure("Invalid order request")
+        }
+    }
+
+    /// Place two orders as a one-cancels-other pair
+    pub fn place_oco(&mut self, first_str: &str, second_str: &str) -> String {
+        match (OrderRequest::from_json_string(first_str), OrderRequest::from_json_string(second_str)) {
+            (Some(first), Some(second)) => summary_status(self.market.place_oco(first, second)),
+            _ => failure("Invalid order request")
+        }
+    }
+
     pub fn buy(&mut self, order_request_str: &str) -> String {
-        let mut order_request: OrderRequest = OrderRequest::from_json_string(order_request_str);
-        order_request.order.kind = OrderKind::BUY;
-        self.market.place_order(order_request).to_json_str()
+        self.place_as(order_request_str, OrderKind::BUY)
     }
 
     pub fn sell(&mut self, order_request_str: &str) -> String {
-        let mut order_request: OrderRequest = OrderRequest::from_json_string(order_request_str);
-        order_request.order.kind = OrderKind::SELL;
-        self.market.place_order(order_request).to_json_str()
+        self.place_as(order_request_str, OrderKind::SELL)
+    }
+
+    /// Buy at whatever the book is selling for. `price_per` is ignored.
+    pub fn market_buy(&mut self, order_request_str: &str) -> String {
+        self.place_as(order_request_str, OrderKind::MARKET_BUY)
     }
 
+    /// Sell at whatever the book is buying for. `price_per` is ignored.
+    pub fn market_sell(&mut self, order_request_str: &str) -> String {
+        self.place_as(order_request_str, OrderKind::MARKET_SELL)
+    }
+
+    /// The book as other traders may see it. Use `dump` for everything.
     pub fn query_ledger(&mut self, item: String) -> String {
 
         let result: Option<Ledger> = self.market.query_ledger(item);
         match result {
-            Some(ledger_copy) => serde_json::to_string(&ledger_copy.to_json()).unwrap(),
+            Some(ledger_copy) => to_json_string(&ledger_copy.to_public_json()),
             None => return "{}".to_string()
         }
     }
 
     pub fn dump(&mut self) -> String {
         let result: HashMap<String, LedgerJSON> = self.market.to_json();
-        serde_json::to_string(&result).unwrap()
+        to_json_string(&result)
     }
 
-    pub fn load(data: String) -> MarketWrapper{
-        let result: HashMap<String, LedgerJSON> = serde_json::from_str(&data).unwrap();
-        MarketWrapper {
-            market: Market::from_json(result)
+    /// Rebuild a market from a `dump`. Malformed data is refused with a failure status.
+    pub fn load(data: String) -> Result<MarketWrapper, String> {
+        match serde_json::from_str::<HashMap<String, LedgerJSON>>(&data) {
+            Ok(result) => Ok(MarketWrapper {
+                market: Market::from_json(result)
+            }),
+            Err(_) => Err(failure("Invalid market data"))
         }
     }
 
     pub fn cancel_order(&mut self, item: String, order: String) -> String {
 
-        match Order::from_json_string(&order) {
-            Some(order) => {
-                let result: Option<Order> = self.market.cancel_order(item, order);
-                match result {
-                    Some(_order) => "{ \"status\": \"SUCCESS\" }".to_string(),
-                    None => "{ \"status\": \"FAILURE\", \"reason\" : \"Order does not exist\" }".to_string()
-                }
+        match serde_json::from_str::<OrderJSON>(&order) {
+            Ok(json) => match Order::from_json(json) {
+                Some(order) => cancellation_status(self.market.cancel_order(item, order)),
+                None => failure("Invalid UUID string")
+            },
+            Err(_) => failure("Invalid order")
+        }
+    }
+
+    pub fn cancel_by_id(&mut self, item: String, order_id: String) -> String {
+        match Uuid::try_parse(&order_id) {
+            Ok(id) => cancellation_status(self.market.cancel_by_id(item, id)),
+            Err(_) => failure("Invalid UUID string")
+        }
+    }
+
+    pub fn cancel(&mut self, order_id: String) -> String {
+        match Uuid::try_parse(&order_id) {
+            Ok(id) => cancellation_status(self.market.cancel(id)),
+            Err(_) => failure("Invalid UUID string")
+        }
+    }
+
+    /// Change a resting order's amount and price, keeping its id
+    pub fn amend_order(&mut self, order_id: String, new_amount: u32, new_price: &str) -> String {
+        match (Uuid::try_parse(&order_id), new_price.parse::<Price>()) {
+            (Ok(id), Ok(price)) => summary_status(self.market.amend_order(id, new_amount, price)),
+            (Err(_), _) => failure("Invalid UUID string"),
+            (_, Err(_)) => failure("Invalid price"),
+        }
+    }
+
+    pub fn halt(&mut self, item: String) -> String {
+        self.market.halt(item);
+        success(None)
+    }
+
+    pub fn resume(&mut self, item: String) -> String {
+        self.market.resume(item);
+        success(None)
+    }
+
+    /// Market times are plain JavaScript numbers, so they must be whole and no larger than
+    /// `Number.MAX_SAFE_INTEGER`
+    pub fn set_time(&mut self, now: f64) -> String {
+        match market_time(now) {
+            Some(now) => {
+                self.market.set_time(now);
+                success(None)
+            }
+            None => failure("Invalid time")
+        }
+    }
+
+    /// Orders placed without an expiry rest for `lifetime` units of market time
+    pub fn set_default_lifetime(&mut self, lifetime: Option<f64>) -> String {
+        match lifetime.map(|x| market_time(x).ok_or(())).transpose() {
+            Ok(lifetime) => {
+                self.market.set_default_lifetime(lifetime);
+                success(None)
+            }
+            Err(()) => failure("Invalid time")
+        }
+    }
+
+    /// Self-trade prevention for orders that do not choose their own, such as
+    /// `CANCEL_OLDEST`. `None` turns it off.
+    pub fn set_self_trade_prevention(&mut self, mode: Option<String>) -> String {
+        match mode.map(|x| serde_json::from_value::<SelfTrade>(serde_json::Value::String(x))).transpose() {
+            Ok(mode) => {
+                self.market.set_self_trade_prevention(mode);
+                success(None)
+            }
+            Err(_) => failure("Invalid self-trade prevention mode")
+        }
+    }
+
+    /// Fees for items without their own, as a JSON fee schedule. `None` stops charging them.
+    pub fn set_fees(&mut self, fees_str: Option<String>) -> String {
+        match fees_str.map(|x| serde_json::from_str::<FeeSchedule>(&x)).transpose() {
+            Ok(fees) => match self.market.set_fees(fees) {
+                Ok(()) => success(None),
+                Err(error) => failure(error),
             },
-            None => "{ \"status\": \"FAILURE\", \"reason\" : \"Invalid UUID string\" }".to_string()
+            Err(_) => failure("Invalid fee schedule")
+        }
+    }
+
+    /// Caps on what each user can have open, as JSON risk limits
+    pub fn set_risk_limits(&mut self, limits_str: &str) -> String {
+        match serde_json::from_str::<RiskLimits>(limits_str) {
+            Ok(limits) => match self.market.set_risk_limits(limits) {
+                Ok(()) => success(None),
+                Err(error) => failure(error),
+            },
+            Err(_) => failure("Invalid risk limits")
+        }
+    }
+
+    /// Advance market time and return the orders that expired, by item
+    pub fn expire_orders(&mut self, now: f64) -> String {
+        let now = match market_time(now) {
+            Some(now) => now,
+            None => return failure("Invalid time")
+        };
+        let expired: HashMap<String, Vec<OrderJSON>> = self
+            .market
+            .expire_orders(now)
+            .into_iter()
+            .map(|(item, orders)| (item, orders.iter().map(|x| x.to_json()).collect()))
+            .collect();
+        to_json_string(&expired)
+    }
+
+    /// Start keeping balances and escrow. Orders then need funds or items to back them.
+    pub fn enable_accounts(&mut self) -> String {
+        self.market.enable_accounts();
+        success(None)
+    }
+
+    pub fn deposit(&mut self, user_id: String, amount: &str) -> String {
+        match amount.parse::<Price>() {
+            Ok(amount) => status(self.market.deposit(user_id, amount)),
+            Err(_) => failure("Invalid price")
+        }
+    }
+
+    pub fn withdraw(&mut self, user_id: String, amount: &str) -> String {
+        match amount.parse::<Price>() {
+            Ok(amount) => status(self.market.withdraw(user_id, amount)),
+            Err(_) => failure("Invalid price")
+        }
+    }
+
+    pub fn deposit_items(&mut self, user_id: String, item: String, amount: u32) -> String {
+        status(self.market.deposit_items(user_id, item, amount))
+    }
+
+    pub fn withdraw_items(&mut self, user_id: String, item: String, amount: u32) -> String {
+        status(self.market.withdraw_items(user_id, item, amount))
+    }
+
+    /// A user's balance, items, and what is held of each
+    pub fn query_account(&mut self, user_id: String) -> String {
+        match self.market.account(&user_id) {
+            Some(account) => to_json_string(account),
+            None => "{}".to_string()
+        }
+    }
+
+    /// Every account, for `load_accounts`. Kept apart from `dump`.
+    pub fn dump_accounts(&mut self) -> String {
+        match self.market.accounts_to_json() {
+            Some(accounts) => to_json_string(&accounts),
+            None => failure(MarketError::AccountsDisabled)
+        }
+    }
+
+    pub fn load_accounts(&mut self, data: String) -> String {
+        match serde_json::from_str::<HashMap<String, Account>>(&data) {
+            Ok(accounts) => {
+                self.market.load_accounts(accounts);
+                success(None)
+            }
+            Err(_) => failure("Invalid account data")
         }
     }
 
     pub fn get_best_buying_price(&mut self, item: String) -> String {
         
         match self.market.get_best_buying_price(item) {
-            Some(order) => serde_json::to_string(&order.to_json()).unwrap(),
+            Some(order) => to_json_string(&order.to_public_json()),
             None => "{}".to_string()
         }
 
@@ -93,13 +295,90 @@ impl MarketWrapper {
 
     pub fn get_best_selling_price(&mut self, item:String) -> String {
         match self.market.get_best_selling_price(item) {
-            Some(order) => serde_json::to_string(&order.to_json()).unwrap(),
+            Some(order) => to_json_string(&order.to_public_json()),
             None => "{}".to_string()
         }
     }
 
 }
 
+impl MarketWrapper {
+    /// Place a request as `kind`, whatever kind it names
+    fn place_as(&mut self, order_request_str: &str, kind: OrderKind) -> String {
+        match OrderRequest::from_json_string(order_request_str) {
+            Some(mut order_request) => {
+                order_request.order.kind = kind;
+                summary_status(self.market.place_order(order_request))
+            }
+            None => failure("Invalid order request")
+        }
+    }
+}
+
+/// Reply to any request that can fail. Failures always carry a `reason`.
+#[derive(Serialize)]
+struct StatusJSON {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<OrderJSON>,
+    /// The other leg of a one-cancels-other pair, cancelled along with `order`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    linked: Option<OrderJSON>,
+}
+
+fn success(order: Option<OrderJSON>) -> String {
+    let status = StatusJSON { status: "SUCCESS", reason: None, order, linked: None };
+    to_json_string(&status)
+}
+
+fn failure(reason: impl std::fmt::Display) -> String {
+    let status = StatusJSON { status: "FAILURE", reason: Some(reason.to_string()), order: None, linked: None };
+    to_json_string(&status)
+}
+
+/// Serialize a reply. Nothing here may panic: a trap inside WASM takes the host process with it.
+fn to_json_string<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| {
+        "{\"status\":\"FAILURE\",\"reason\":\"Could not serialize reply\"}".to_string()
+    })
+}
+
+fn summary_status(result: Result<Summary, MarketError>) -> String {
+    match result {
+        Ok(summary) => to_json_string(&summary.to_json()),
+        Err(error) => failure(error)
+    }
+}
+
+fn status(result: Result<(), MarketError>) -> String {
+    match result {
+        Ok(()) => success(None),
+        Err(error) => failure(error)
+    }
+}
+
+/// A market time from JavaScript: a whole, non-negative number small enough to be exact
+fn market_time(value: f64) -> Option<u64> {
+    const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+    let whole = value.is_finite() && value.fract() == 0.0;
+    (whole && (0.0..=MAX_SAFE_INTEGER).contains(&value)).then_some(value as u64)
+}
+
+/// Report a cancellation along with the remainder that was taken off the book, and that of
+/// the other leg of its pair if it went too
+fn cancellation_status(result: Result<Vec<Order>, MarketError>) -> String {
+    match result {
+        Ok(orders) => {
+            let mut orders = orders.iter().map(|x| x.to_json());
+            let status = StatusJSON { status: "SUCCESS", reason: None, order: orders.next(), linked: orders.next() };
+            to_json_string(&status)
+        },
+        Err(error) => failure(error)
+    }
+}
+
 #[wasm_bindgen]
 pub fn test() -> String {
     "Module works".to_string()
@@ -0,0 +1,192 @@
+use MarketCore::{self, structs::{CancelReason, FeeSchedule, OrderRequest, OrderKind}, market::Market, price::Price, error::MarketError};
+
+fn market_with_accounts() -> Market {
+    let mut exchange = Market::new();
+    exchange.enable_accounts();
+    exchange.deposit("ALICE".to_string(), Price::from(1000)).unwrap();
+    exchange.deposit_items("BOB".to_string(), "potion".to_string(), 20).unwrap();
+    exchange
+}
+
+#[test]
+fn test_orders_need_backing() {
+
+    let mut exchange = market_with_accounts();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 11, Price::from(100));
+    let order2 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 21, Price::from(100));
+    let order3 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::BUY, 1, Price::from(1));
+
+    assert_eq!(exchange.place_order(order1), Err(MarketError::InsufficientFunds));
+    assert_eq!(exchange.place_order(order2), Err(MarketError::InsufficientItems("POTION".to_string())));
+    assert_eq!(exchange.place_order(order3), Err(MarketError::InsufficientFunds));
+
+    let mut disabled = Market::new();
+    assert_eq!(disabled.deposit("ALICE".to_string(), Price::from(1)), Err(MarketError::AccountsDisabled));
+
+}
+
+#[test]
+fn test_escrow_and_settlement() {
+
+    let mut exchange = market_with_accounts();
+
+    // A resting buy holds what it would pay
+    let order1 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 8, Price::from(100));
+    let buy = exchange.place_order(order1).unwrap().created.unwrap();
+
+    let alice = exchange.account("ALICE").unwrap();
+    assert_eq!((alice.balance, alice.held, alice.available()), (Price::from(1000), Price::from(800), Price::from(200)));
+    assert_eq!(exchange.withdraw("ALICE".to_string(), Price::from(300)), Err(MarketError::InsufficientFunds));
+
+    // Selling into it moves gold one way and potions the other, at the resting price
+    let order2 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 5, Price::from(90));
+    exchange.place_order(order2).unwrap();
+
+    let alice = exchange.account("ALICE").unwrap();
+    assert_eq!((alice.balance, alice.held), (Price::from(500), Price::from(300)));
+    assert_eq!(alice.items["POTION"], 5);
+
+    let bob = exchange.account("BOB").unwrap();
+    assert_eq!((bob.balance, bob.items["POTION"]), (Price::from(500), 15));
+
+    // Cancelling releases the rest
+    exchange.cancel(buy.id).unwrap();
+    assert_eq!(exchange.account("ALICE").unwrap().held, Price::from(0));
+    exchange.withdraw("ALICE".to_string(), Price::from(500)).unwrap();
+
+}
+
+#[test]
+fn test_sells_hold_items() {
+
+    let mut exchange = market_with_accounts();
+
+    let mut order1 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 15, Price::from(50));
+    order1.order.expires_at = Some(10);
+    exchange.place_order(order1).unwrap();
+
+    assert_eq!(exchange.account("BOB").unwrap().available_items("POTION"), 5);
+    assert_eq!(exchange.withdraw_items("BOB".to_string(), "POTION".to_string(), 6), Err(MarketError::InsufficientItems("POTION".to_string())));
+
+    // Expiry releases them
+    exchange.expire_orders(10);
+    exchange.withdraw_items("BOB".to_string(), "POTION".to_string(), 20).unwrap();
+
+}
+
+#[test]
+fn test_market_buy_spends_what_is_available() {
+
+    let mut exchange = market_with_accounts();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 20, Price::from(300));
+    exchange.place_order(order1).unwrap();
+
+    let order2 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.filled(), 3);
+    assert_eq!((summary.spent, summary.budget_left), (None, None));
+    assert_eq!(exchange.account("ALICE").unwrap().balance, Price::from(100));
+
+    // Nothing left to spend
+    let order4 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 1, Price::from(0));
+    let summary = exchange.place_order(order4).unwrap();
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Budget);
+
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::STOP_BUY, 1, Price::from(0));
+    order3.order.trigger_price = Some(Price::from(400));
+    assert_eq!(exchange.place_order(order3), Err(MarketError::InvalidOrder("Stop market buys cannot be held in escrow; use a stop-limit buy".to_string())));
+
+}
+
+#[test]
+fn test_one_cancels_other_holds_once() {
+
+    let mut exchange = market_with_accounts();
+
+    let take_profit = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 20, Price::from(120));
+    let mut stop_loss = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::STOP_SELL, 20, Price::from(0));
+    stop_loss.order.trigger_price = Some(Price::from(80));
+    let first = exchange.place_oco(take_profit, stop_loss).unwrap().created.unwrap();
+
+    assert_eq!(exchange.account("BOB").unwrap().held_items["POTION"], 20);
+
+    exchange.cancel(first.id).unwrap();
+    assert_eq!(exchange.account("BOB").unwrap().held_items["POTION"], 0);
+
+}
+
+#[test]
+fn test_accounts_reload() {
+
+    let mut exchange = market_with_accounts();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 5, Price::from(100));
+    exchange.place_order(order1).unwrap();
+
+    let mut reloaded = Market::from_json(exchange.to_json());
+    reloaded.load_accounts(exchange.accounts_to_json().unwrap());
+
+    assert_eq!(reloaded.account("ALICE"), exchange.account("ALICE"));
+    assert_eq!(reloaded.account("ALICE").unwrap().held, Price::from(500));
+
+}
+
+#[test]
+fn test_fees_are_settled() {
+
+    let mut exchange = market_with_accounts();
+    exchange.set_fees(Some(FeeSchedule { maker_bps: 100, taker_bps: 200, ..FeeSchedule::default() })).unwrap();
+
+    // A resting buy holds its fee at the higher rate as well
+    let order1 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 10, Price::from(99));
+    assert_eq!(exchange.place_order(order1), Err(MarketError::InsufficientFunds));
+
+    let order2 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 10, Price::from(50));
+    exchange.place_order(order2).unwrap();
+    assert_eq!(exchange.account("ALICE").unwrap().held, Price::from(510));
+
+    // The seller takes, and pays its fee out of what it is paid
+    let order3 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 10, Price::from(50));
+    exchange.place_order(order3).unwrap();
+
+    let alice = exchange.account("ALICE").unwrap();
+    assert_eq!((alice.balance, alice.held), (Price::from(495), Price::from(0)));
+    assert_eq!(exchange.account("BOB").unwrap().balance, Price::from(490));
+
+}
+
+#[test]
+fn test_minimum_fees_are_backed() {
+
+    let mut exchange = market_with_accounts();
+    exchange.set_fees(Some(FeeSchedule { min_fee: Some(Price::from(1)), ..FeeSchedule::default() })).unwrap();
+    exchange.deposit("CAROL".to_string(), Price::from(20)).unwrap();
+
+    // Each unit could trade alone, so a buy holds a minimum fee for every one
+    let order1 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::BUY, 10, Price::from(1));
+    let order2 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::BUY, 10, Price::from(1));
+    exchange.place_order(order1).unwrap();
+    assert_eq!(exchange.account("CAROL").unwrap().held, Price::from(20));
+    assert_eq!(exchange.place_order(order2), Err(MarketError::InsufficientFunds));
+
+    let order3 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 10, Price::from(1));
+    exchange.place_order(order3).unwrap();
+    assert_eq!(exchange.account("CAROL").unwrap().balance, Price::from(9));
+    assert_eq!(exchange.account("BOB").unwrap().balance, Price::from(9));
+
+    // A market buy pays a minimum fee on each trade, and buys only what that leaves room for
+    for _ in 0..5 {
+        let order = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 1, Price::from(1));
+        exchange.place_order(order).unwrap();
+    }
+    let order4 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 5, Price::from(0));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.filled(), 4);
+    assert_eq!(exchange.account("CAROL").unwrap().balance, Price::from(1));
+    assert_eq!(exchange.account("BOB").unwrap().balance, Price::from(9));
+
+}
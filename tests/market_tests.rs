@@ -1,7 +1,7 @@
 use std::time::Instant;
 use rand::{seq::SliceRandom, Rng}; // 0.7.2
 
-use MarketCore::{self, structs::{OrderRequest, OrderKind}, market::Market};
+use MarketCore::{self, structs::{Bracket, CancelReason, FeeSchedule, ItemSpec, OrderRequest, OrderKind, PostOnly, RiskLimits, SelfTrade, Slippage, TimeInForce, Trail, Transaction}, market::Market, price::Price, error::MarketError};
 use uuid::Uuid;
 use wildmatch::WildMatch;
 
@@ -10,15 +10,15 @@ fn test_buy() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, Price::from(14));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
 
-    let buy_orders = &exchange.map.get("CORN").unwrap().buy_orders;
+    let buy_orders = exchange.map.get("CORN").unwrap().buy_orders();
 
-    let test_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 32, price_per: OrderedFloat(12.0) }, Order { id: *, user_id: \"ALICE\", kind: BUY, amount: 12, price_per: OrderedFloat(14.0) }]";
+    let test_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 32, price_per: 12.00, seq: * }, Order { id: *, user_id: \"ALICE\", kind: BUY, amount: 12, price_per: 14.00, seq: * }]";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", buy_orders).as_str()));
 
@@ -29,15 +29,15 @@ fn test_sell() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 20, 10.0);
-    let order2 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 14, 15.0);
+    let order1 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 20, Price::from(10));
+    let order2 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 14, Price::from(15));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
 
-    let sell_orders = &exchange.map.get("CORN").unwrap().sell_orders;
+    let sell_orders = exchange.map.get("CORN").unwrap().sell_orders();
 
-    let test_str = "[Order { id: *, user_id: \"CAROL\", kind: SELL, amount: 20, price_per: OrderedFloat(10.0) }, Order { id: *, user_id: \"CAROL\", kind: SELL, amount: 14, price_per: OrderedFloat(15.0) }]";
+    let test_str = "[Order { id: *, user_id: \"CAROL\", kind: SELL, amount: 20, price_per: 10.00, seq: * }, Order { id: *, user_id: \"CAROL\", kind: SELL, amount: 14, price_per: 15.00, seq: * }]";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", sell_orders).as_str()));
 
@@ -48,23 +48,23 @@ fn test_buy_and_sell() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, 14.0);
-    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 20, 10.0);
-    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 14, 15.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, Price::from(14));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 20, Price::from(10));
+    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 14, Price::from(15));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
-    exchange.place_order(order3);
-    exchange.place_order(order4);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+    exchange.place_order(order4).unwrap();
 
     let ledger = exchange.map.get("CORN").unwrap();
 
-    let buy_orders = &ledger.buy_orders;
-    let sell_orders = &ledger.sell_orders;
+    let buy_orders = ledger.buy_orders();
+    let sell_orders = ledger.sell_orders();
 
-    let buy_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 24, price_per: OrderedFloat(12.0) }]";
-    let sell_str = "[Order { id: *, user_id: \"CAROL\", kind: SELL, amount: 14, price_per: OrderedFloat(15.0) }]";
+    let buy_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 24, price_per: 12.00, seq: * }]";
+    let sell_str = "[Order { id: *, user_id: \"CAROL\", kind: SELL, amount: 14, price_per: 15.00, seq: * }]";
 
     assert!(WildMatch::new(buy_str).matches(format!("{:?}", buy_orders).as_str()));
     assert!(WildMatch::new(sell_str).matches(format!("{:?}", sell_orders).as_str()));
@@ -76,24 +76,24 @@ fn test_buy_and_sell_and_buy() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, 14.0);
-    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 20, 10.0);
-    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 14, 15.0);
-    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 14, 16.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, Price::from(14));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 20, Price::from(10));
+    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::SELL, 14, Price::from(15));
+    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 14, Price::from(16));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
-    exchange.place_order(order3);
-    exchange.place_order(order4);
-    exchange.place_order(order5);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+    exchange.place_order(order4).unwrap();
+    exchange.place_order(order5).unwrap();
 
     let ledger = exchange.map.get("CORN").unwrap();
 
-    let buy_orders = &ledger.buy_orders;
-    let sell_orders = &ledger.sell_orders;
+    let buy_orders = ledger.buy_orders();
+    let sell_orders = ledger.sell_orders();
 
-    let buy_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 24, price_per: OrderedFloat(12.0) }]";
+    let buy_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 24, price_per: 12.00, seq: * }]";
     let sell_str = "[]";
 
     assert!(WildMatch::new(buy_str).matches(format!("{:?}", buy_orders).as_str()));
@@ -106,22 +106,22 @@ fn test_summary() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, 14.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::SELL, 32, 12.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::SELL, 32, Price::from(12));
 
-    exchange.place_order(order1);
-    let summary = exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
 
     println!("{:?}", summary);
 
-    // Transaction should be at buyer price
-    let transactions_str = "[Transaction { buyer: \"ALICE\", seller: \"BOB\", amount: 12, price_per: 14.0 }]";
+    // Transaction should be at the resting order's price
+    let transactions_str = "[Transaction { buyer: \"BOB\", seller: \"ALICE\", amount: 12, price_per: 14.00, buyer_fee: 0, seller_fee: 0 }]";
 
     // Bob's purchase order should be closed out w/ amount=0
-    let to_update_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 0, price_per: OrderedFloat(14.0) }]";
+    let to_update_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 0, price_per: 14.00, seq: * }]";
 
     //Alice's open order will be created with the remainder of the items
-    let created_str = "Some(Order { id: *, user_id: \"ALICE\", kind: SELL, amount: 20, price_per: OrderedFloat(12.0) })";
+    let created_str = "Some(Order { id: *, user_id: \"ALICE\", kind: SELL, amount: 20, price_per: 12.00, seq: * })";
 
     assert_eq!("CORN", summary.key);
     println!("{:?}", summary.transactions);
@@ -136,15 +136,15 @@ fn test_cancel_buy_order_full() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
 
-    let summary = exchange.place_order(order1);
+    let summary = exchange.place_order(order1).unwrap();
     let order = summary.created.unwrap();
     let item = summary.key;
 
-    exchange.cancel_order(item.clone(), order);
+    exchange.cancel_order(item.clone(), order).unwrap();
 
-    assert_eq!(exchange.map.get(&item).unwrap().buy_orders.len(), 0);
+    assert_eq!(exchange.map.get(&item).unwrap().buy_orders().len(), 0);
 
 }
 
@@ -153,15 +153,15 @@ fn test_cancel_sell_order_full() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 12, Price::from(14));
 
-    let summary = exchange.place_order(order1);
+    let summary = exchange.place_order(order1).unwrap();
     let order = summary.created.unwrap();
     let item = summary.key;
 
-    exchange.cancel_order(item.clone(), order);
+    exchange.cancel_order(item.clone(), order).unwrap();
 
-    assert_eq!(exchange.map.get(&item).unwrap().sell_orders.len(), 0);
+    assert_eq!(exchange.map.get(&item).unwrap().sell_orders().len(), 0);
 
 }
 
@@ -170,18 +170,18 @@ fn test_cancel_partial() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, 14.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 6, 12.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 6, Price::from(12));
 
-    exchange.place_order(order1);
-    let summary = exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
 
     let order = summary.to_update[0].clone();
     let item = summary.key;
 
-    exchange.cancel_order(item.clone(), order);
+    exchange.cancel_order(item.clone(), order).unwrap();
 
-    assert_eq!(exchange.map.get(&item).unwrap().buy_orders.len(), 0);
+    assert_eq!(exchange.map.get(&item).unwrap().buy_orders().len(), 0);
 
 }
 
@@ -190,9 +190,9 @@ fn test_cancel_failure_bad_item() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
 
-    let summary = exchange.place_order(order1);
+    let summary = exchange.place_order(order1).unwrap();
 
     let order = summary.created.unwrap();
     let item = summary.key;
@@ -200,9 +200,11 @@ fn test_cancel_failure_bad_item() {
     println!("{:?}", order);
     println!("{:?}", exchange.query_ledger("CORN".to_string()).unwrap());
 
-    exchange.cancel_order("BRUH".to_string(), order);
+    let result = exchange.cancel_order("BRUH".to_string(), order);
 
-    assert_eq!(exchange.map.get(&item).unwrap().buy_orders.len(), 1);
+    assert_eq!(result, Err(MarketError::UnknownItem("BRUH".to_string())));
+
+    assert_eq!(exchange.map.get(&item).unwrap().buy_orders().len(), 1);
 
 }
 
@@ -211,9 +213,9 @@ fn test_cancel_failure_bad_order() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
 
-    let summary = exchange.place_order(order1);
+    let summary = exchange.place_order(order1).unwrap();
 
     let mut order = summary.created.unwrap();
     order.id = Uuid::new_v4();
@@ -222,9 +224,89 @@ fn test_cancel_failure_bad_order() {
     println!("{:?}", order);
     println!("{:?}", exchange.query_ledger("CORN".to_string()).unwrap());
 
-    exchange.cancel_order("CORN".to_string(), order);
+    let result = exchange.cancel_order("CORN".to_string(), order);
+
+    assert_eq!(result, Err(MarketError::OrderNotFound));
+
+    assert_eq!(exchange.map.get(&item).unwrap().buy_orders().len(), 1);
+
+}
+
+#[test]
+fn test_cancel_by_id() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(12));
+
+    let summary = exchange.place_order(order1).unwrap();
+    let id = summary.created.unwrap().id;
+    exchange.place_order(order2).unwrap();
+
+    // Only the unfilled remainder comes back
+    let cancelled = exchange.cancel_by_id("corn".to_string(), id).unwrap();
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].id, id);
+    assert_eq!(cancelled[0].amount, 7);
+    assert_eq!(exchange.map.get("CORN").unwrap().buy_orders().len(), 0);
+
+    assert_eq!(exchange.cancel_by_id("CORN".to_string(), id), Err(MarketError::OrderNotFound));
+
+}
+
+#[test]
+fn test_cancel_within_a_price_level() {
+
+    let mut exchange = Market::new();
+
+    let mut ids = vec![];
+    for i in 0..200 {
+        let order = OrderRequest::new(format!("SELLER{}", i), "CORN".to_string(), OrderKind::SELL, 1, Price::from(10));
+        ids.push(exchange.place_order(order).unwrap().created.unwrap().id);
+    }
+
+    // Cancel all but every tenth, from the back and the middle as well as the front
+    for (i, id) in ids.iter().enumerate().rev() {
+        if i % 10 != 0 {
+            exchange.cancel(*id).unwrap();
+        }
+    }
+
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert_eq!(ledger.sell_orders().len(), 20);
+    assert_eq!(ledger.get(&ids[50]).unwrap().user_id, "SELLER50");
+
+    // What is left still trades in arrival order
+    let order = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 3, Price::from(10));
+    let summary = exchange.place_order(order).unwrap();
+    let sellers: Vec<&str> = summary.transactions.iter().map(|x| x.seller.as_str()).collect();
+    assert_eq!(sellers, vec!["SELLER0", "SELLER10", "SELLER20"]);
+
+    assert_eq!(exchange.cancel(ids[30]).unwrap()[0].user_id, "SELLER30");
+    assert_eq!(exchange.cancel(ids[30]), Err(MarketError::OrderNotFound));
+
+}
+
+#[test]
+fn test_cancel_without_item() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
+    let order2 = OrderRequest::new("BOB".to_string(), "WHEAT".to_string(), OrderKind::SELL, 12, Price::from(14));
+
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
+    let id = summary.created.unwrap().id;
 
-    assert_eq!(exchange.map.get(&item).unwrap().buy_orders.len(), 1);
+    assert_eq!(exchange.cancel(Uuid::new_v4()), Err(MarketError::OrderNotFound));
+    assert_eq!(exchange.cancel_by_id("CORN".to_string(), id), Err(MarketError::OrderNotFound));
+
+    let cancelled = exchange.cancel(id).unwrap();
+    assert_eq!(cancelled[0].user_id, "BOB");
+    assert_eq!(exchange.map.get("WHEAT").unwrap().sell_orders().len(), 0);
+    assert_eq!(exchange.map.get("CORN").unwrap().buy_orders().len(), 1);
 
 }
 
@@ -233,13 +315,13 @@ fn test_query() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, Price::from(14));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
 
-    let test_str = "Some(Ledger { buy_orders: [Order { id: *, user_id: \"BOB\", kind: BUY, amount: 32, price_per: OrderedFloat(12.0) }, Order { id: *, user_id: \"ALICE\", kind: BUY, amount: 12, price_per: OrderedFloat(14.0) }], sell_orders: [] })";
+    let test_str = "Some(Ledger { buy_orders: [Order { id: *, user_id: \"BOB\", kind: BUY, amount: 32, price_per: 12.00, seq: * }, Order { id: *, user_id: \"ALICE\", kind: BUY, amount: 12, price_per: 14.00, seq: * }], sell_orders: [], sequence: 2, * })";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", exchange.query_ledger("CORN".to_string())).as_str()));
     assert_eq!(None, exchange.query_ledger("STUFF".to_string()));
@@ -251,17 +333,17 @@ fn test_market_buy() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::SELL, 12, 14.0);
-    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::MARKET_BUY, 34, 0.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::SELL, 12, Price::from(14));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::MARKET_BUY, 34, Price::from(0));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
-    exchange.place_order(order3);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
 
-    let sell_orders = &exchange.map.get("CORN").unwrap().sell_orders;
+    let sell_orders = exchange.map.get("CORN").unwrap().sell_orders();
 
-    let test_str = "[Order { id: *, user_id: \"ALICE\", kind: SELL, amount: 10, price_per: OrderedFloat(14.0) }]";
+    let test_str = "[Order { id: *, user_id: \"ALICE\", kind: SELL, amount: 10, price_per: 14.00, seq: * }]";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", sell_orders).as_str()));
 
@@ -272,17 +354,17 @@ fn test_market_sell() {
 
     let mut exchange = Market::new();
 
-    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, 14.0);
-    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::MARKET_SELL, 34, 0.0);
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(),OrderKind::BUY, 12, Price::from(14));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(),OrderKind::MARKET_SELL, 34, Price::from(0));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
-    exchange.place_order(order3);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
 
-    let buy_orders = &exchange.map.get("CORN").unwrap().buy_orders;
+    let buy_orders = exchange.map.get("CORN").unwrap().buy_orders();
 
-    let test_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 10, price_per: OrderedFloat(12.0) }]";
+    let test_str = "[Order { id: *, user_id: \"BOB\", kind: BUY, amount: 10, price_per: 12.00, seq: * }]";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", buy_orders).as_str()));
 
@@ -294,15 +376,15 @@ fn test_sell_price() {
     let mut exchange = Market::new();
     let item = "CORN".to_string();
 
-    let order1 = OrderRequest::new("BOB".to_string(), item.clone(), OrderKind::SELL, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), item.clone(),OrderKind::SELL, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), item.clone(), OrderKind::SELL, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), item.clone(),OrderKind::SELL, 12, Price::from(14));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
 
     let best_order = exchange.get_best_selling_price(item).unwrap();
 
-    let test_str = "Order { id: *, user_id: \"BOB\", kind: SELL, amount: 32, price_per: OrderedFloat(12.0) }";
+    let test_str = "Order { id: *, user_id: \"BOB\", kind: SELL, amount: 32, price_per: 12.00, seq: * }";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", best_order).as_str()));
 
@@ -314,20 +396,364 @@ fn test_buy_price() {
     let mut exchange = Market::new();
     let item = "CORN".to_string();
 
-    let order1 = OrderRequest::new("BOB".to_string(), item.clone(), OrderKind::BUY, 32, 12.0);
-    let order2 = OrderRequest::new("ALICE".to_string(), item.clone(),OrderKind::BUY, 12, 14.0);
+    let order1 = OrderRequest::new("BOB".to_string(), item.clone(), OrderKind::BUY, 32, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), item.clone(),OrderKind::BUY, 12, Price::from(14));
 
-    exchange.place_order(order1);
-    exchange.place_order(order2);
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
 
     let best_order = exchange.get_best_buying_price(item).unwrap();
 
-    let test_str = "Order { id: *, user_id: \"ALICE\", kind: BUY, amount: 12, price_per: OrderedFloat(14.0) }";
+    let test_str = "Order { id: *, user_id: \"ALICE\", kind: BUY, amount: 12, price_per: 14.00, seq: * }";
 
     assert!(WildMatch::new(test_str).matches(format!("{:?}", best_order).as_str()));
 
 }
 
+#[test]
+fn test_time_priority_buy() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::BUY, 15, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    let summary = exchange.place_order(order3).unwrap();
+
+    // Bob listed first, so he is filled completely before Alice
+    assert_eq!(summary.to_update.len(), 2);
+    assert_eq!(summary.to_update[0].user_id, "BOB");
+    assert_eq!(summary.to_update[0].amount, 0);
+    assert_eq!(summary.to_update[1].user_id, "ALICE");
+    assert_eq!(summary.to_update[1].amount, 5);
+
+    let sell_orders = exchange.map.get("CORN").unwrap().sell_orders();
+    let test_str = "[Order { id: *, user_id: \"ALICE\", kind: SELL, amount: 5, price_per: 12.00, seq: 1, * }]";
+    assert!(WildMatch::new(test_str).matches(format!("{:?}", sell_orders).as_str()));
+
+}
+
+#[test]
+fn test_time_priority_sell() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let order3 = OrderRequest::new("DAVE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::SELL, 15, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.to_update.len(), 2);
+    assert_eq!(summary.to_update[0].user_id, "BOB");
+    assert_eq!(summary.to_update[1].user_id, "ALICE");
+
+    let best_order = exchange.get_best_buying_price("CORN".to_string()).unwrap();
+    assert_eq!(best_order.user_id, "ALICE");
+    assert_eq!(best_order.amount, 5);
+
+}
+
+#[test]
+fn test_price_before_time() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(11));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    let summary = exchange.place_order(order3).unwrap();
+
+    // A better price still beats an older order
+    assert_eq!(summary.to_update[0].user_id, "ALICE");
+
+}
+
+#[test]
+fn test_time_priority_market_orders() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(10));
+    let order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(10));
+    let order5 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 5, Price::from(0));
+    let order6 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::MARKET_SELL, 5, Price::from(0));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+    exchange.place_order(order4).unwrap();
+
+    let summary = exchange.place_order(order5).unwrap();
+    assert_eq!(summary.to_update[0].user_id, "BOB");
+    assert_eq!(summary.to_update[0].kind, OrderKind::SELL);
+
+    let summary = exchange.place_order(order6).unwrap();
+    assert_eq!(summary.to_update[0].user_id, "BOB");
+    assert_eq!(summary.to_update[0].kind, OrderKind::BUY);
+
+}
+
+#[test]
+fn test_time_priority_survives_reload() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    let mut exchange = Market::from_json(exchange.to_json());
+
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert_eq!(summary.to_update[0].user_id, "BOB");
+    assert_eq!(summary.created, None);
+
+}
+
+#[test]
+fn test_sweep_price_levels() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(11));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order3 = OrderRequest::new("DAVE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(13));
+    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::BUY, 25, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+    let summary = exchange.place_order(order4).unwrap();
+
+    // Both crossing levels are emptied, the 13.00 level is untouched
+    assert_eq!(summary.transactions.len(), 2);
+    assert_eq!(summary.created.unwrap().amount, 5);
+
+    let ledger = exchange.map.get("CORN").unwrap();
+    assert_eq!(ledger.best_sell().unwrap().user_id, "DAVE");
+    assert_eq!(ledger.best_buy().unwrap().user_id, "CAROL");
+    assert_eq!(ledger.sell_orders().len(), 1);
+
+}
+
+#[test]
+fn test_cancel_keeps_level_order() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order3 = OrderRequest::new("DAVE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+
+    let cancelled = exchange.cancel_order("CORN".to_string(), summary.created.unwrap()).unwrap();
+    assert_eq!(cancelled[0].user_id, "ALICE");
+
+    let sell_orders = exchange.map.get("CORN").unwrap().sell_orders();
+    let users: Vec<&str> = sell_orders.iter().map(|x| x.user_id.as_str()).collect();
+    assert_eq!(users, vec!["BOB", "DAVE"]);
+
+    // The remaining orders still fill in arrival order
+    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::BUY, 20, Price::from(12));
+    let summary = exchange.place_order(order4).unwrap();
+    assert_eq!(summary.to_update[1].user_id, "DAVE");
+    assert!(exchange.map.get("CORN").unwrap().sell_orders().is_empty());
+
+}
+
+#[test]
+fn test_item_decimals() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("gold_ore".to_string(), ItemSpec { decimals: 1, ..ItemSpec::default() }).unwrap();
+
+    let buy = OrderRequest::new("BOB".to_string(), "GOLD_ORE".to_string(), OrderKind::BUY, 10, "12.19".parse().unwrap());
+    let sell = OrderRequest::new("ALICE".to_string(), "GOLD_ORE".to_string(), OrderKind::SELL, 10, "12.11".parse().unwrap());
+
+    // Prices are quoted in the item's decimals, rounded in the trader's favour
+    let summary = exchange.place_order(buy).unwrap();
+    assert_eq!(summary.created.unwrap().price_per.to_string(), "12.1");
+    let summary = exchange.place_order(sell).unwrap();
+    assert_eq!(summary.created.unwrap().price_per.to_string(), "12.2");
+
+    // Unregistered items use two decimals
+    let buy = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, "0.1".parse().unwrap());
+    let summary = exchange.place_order(buy).unwrap();
+    assert_eq!(summary.created.unwrap().price_per.to_string(), "0.10");
+
+}
+
+#[test]
+fn test_exact_prices() {
+
+    let mut exchange = Market::new();
+
+    let sell = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, "0.3".parse().unwrap());
+    let buy = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, "0.30".parse().unwrap());
+
+    exchange.place_order(sell).unwrap();
+    let summary = exchange.place_order(buy).unwrap();
+
+    // Equal prices written differently still cross
+    assert_eq!(summary.transactions.len(), 1);
+    assert_eq!(summary.transactions[0].price_per, Price::new(3, 1));
+
+}
+
+#[test]
+fn test_item_spec_rejections() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("ARROWS".to_string(), ItemSpec {
+        tick_size: Some("0.05".parse().unwrap()),
+        lot_size: 100,
+        max_quantity: Some(1000),
+        min_price: Some(Price::from(1)),
+        max_price: Some(Price::from(50)),
+        ..ItemSpec::default()
+    }).unwrap();
+
+    let cases = vec![
+        (0, "2.00", "Amount must be at least 1"),
+        (2000, "2.00", "Amount must be at most 1000"),
+        (150, "2.00", "Amount must be a multiple of the lot size 100"),
+        (100, "2.01", "Price must be a multiple of the tick size 0.05"),
+        (100, "0.50", "Price must be at least 1"),
+        (100, "55", "Price must be at most 50"),
+    ];
+
+    for (amount, price, reason) in cases {
+        let order = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::BUY, amount, price.parse().unwrap());
+        let error = exchange.place_order(order).unwrap_err();
+
+        assert_eq!(error.to_string(), reason);
+    }
+
+    // Rejected orders never reach the book
+    assert!(exchange.query_ledger("ARROWS".to_string()).is_none());
+
+    let order = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::BUY, 300, "2.05".parse().unwrap());
+    let summary = exchange.place_order(order).unwrap();
+    assert_eq!(summary.created.unwrap().amount, 300);
+
+}
+
+#[test]
+fn test_item_spec_market_orders() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("ARROWS".to_string(), ItemSpec {
+        tick_size: Some(Price::from(1)),
+        lot_size: 10,
+        ..ItemSpec::default()
+    }).unwrap();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::SELL, 50, Price::from(3));
+    let order2 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 15, Price::from(0));
+    let order3 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 20, "0.5".parse().unwrap());
+
+    exchange.place_order(order1).unwrap();
+
+    // Lot size applies to market orders, ticks do not
+    let error = exchange.place_order(order2).unwrap_err();
+    assert_eq!(error, MarketError::InvalidQuantity("Amount must be a multiple of the lot size 10".to_string()));
+
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.transactions[0].amount, 20);
+
+}
+
+#[test]
+fn test_invalid_orders() {
+
+    let mut exchange = Market::new();
+
+    let zero_amount = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 0, Price::from(12));
+    let zero_price = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(0));
+    let negative_price = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(-3));
+    let unknown_item = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+
+    assert_eq!(exchange.place_order(zero_amount), Err(MarketError::InvalidQuantity("Amount must be at least 1".to_string())));
+    assert_eq!(exchange.place_order(zero_price), Err(MarketError::InvalidPrice("Price must be positive".to_string())));
+    assert_eq!(exchange.place_order(negative_price), Err(MarketError::InvalidPrice("Price must be positive".to_string())));
+    assert!(exchange.place_order(unknown_item).is_ok());
+
+    // Rounded down to the item's decimals before it is checked
+    let too_small = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 1, "0.001".parse().unwrap());
+    assert_eq!(exchange.place_order(too_small), Err(MarketError::InvalidPrice("Price must be positive".to_string())));
+
+    assert_eq!(exchange.query_ledger("CORN".to_string()), None);
+
+    let bad_spec = ItemSpec { tick_size: Some(Price::from(0)), ..ItemSpec::default() };
+    assert_eq!(exchange.register_item("CORN".to_string(), bad_spec), Err(MarketError::InvalidSpec("Tick size must be positive".to_string())));
+
+}
+
+#[test]
+fn test_cancel_not_owner() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
+
+    let summary = exchange.place_order(order1).unwrap();
+    let mut order = summary.created.unwrap();
+    order.user_id = "MALLORY".to_string();
+
+    let result = exchange.cancel_order("CORN".to_string(), order);
+
+    assert_eq!(result, Err(MarketError::NotOwner));
+    assert_eq!(exchange.map.get("CORN").unwrap().buy_orders().len(), 1);
+
+}
+
+#[test]
+fn test_halt() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(14));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 12, Price::from(14));
+    let order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 12, Price::from(14));
+
+    let summary = exchange.place_order(order1).unwrap();
+    exchange.halt("corn".to_string());
+
+    assert_eq!(exchange.place_order(order2), Err(MarketError::Halted("CORN".to_string())));
+
+    // Cancels still go through while halted
+    let order = summary.created.unwrap();
+    exchange.cancel_order("CORN".to_string(), order).unwrap();
+
+    // Other items keep trading
+    exchange.place_order(OrderRequest::new("BOB".to_string(), "WHEAT".to_string(), OrderKind::BUY, 12, Price::from(14))).unwrap();
+
+    exchange.resume("CORN".to_string());
+    let summary = exchange.place_order(order3).unwrap();
+    assert!(summary.created.is_some());
+
+}
+
 #[test]
 #[ignore = "Only run manually"]
 fn speed_test() {
@@ -357,10 +783,10 @@ fn speed_test() {
         let kind = if rand < 0.5 { OrderKind::BUY } else { OrderKind::SELL };
 
         let amount = rng.gen_range(1..1000);
-        let price_per = rng.gen_range(1.0..25.0);
+        let price_per = Price::new(rng.gen_range(100..2500), 2);
 
         let order = OrderRequest::new(user, item, kind, amount, price_per);
-        exchange.place_order(order);
+        exchange.place_order(order).unwrap();
 
     }
 
@@ -369,3 +795,1400 @@ fn speed_test() {
     println!("{} trades/sec", (num_trades / elapsed) * 1000);
 
 }
+
+#[test]
+fn test_market_order_no_liquidity() {
+
+    let mut exchange = Market::new();
+
+    // No book at all
+    let order1 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+    let summary = exchange.place_order(order1).unwrap();
+
+    assert_eq!(summary.filled(), 0);
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::NoLiquidity);
+    assert_eq!(summary.cancelled[0].order.amount, 10);
+    assert_eq!(exchange.query_ledger("CORN".to_string()), None);
+
+    // A book with nothing on the side the order needs
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 4, Price::from(12));
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::MARKET_SELL, 10, Price::from(0));
+    exchange.place_order(order2).unwrap();
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert_eq!(summary.filled(), 0);
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::NoLiquidity);
+
+    // A partial fill drops the rest
+    let order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.filled(), 4);
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.cancelled[0].order.amount, 6);
+    assert!(exchange.query_ledger("CORN".to_string()).unwrap().sell_orders().is_empty());
+
+}
+
+#[test]
+fn test_transaction_buy_crosses() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(15));
+
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.transactions, vec![Transaction::new("ALICE".to_string(), "BOB".to_string(), 10, Price::from(12))]);
+
+}
+
+#[test]
+fn test_transaction_sell_crosses() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(15));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.transactions, vec![Transaction::new("BOB".to_string(), "ALICE".to_string(), 10, Price::from(15))]);
+
+}
+
+#[test]
+fn test_transaction_market_buy() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.transactions, vec![Transaction::new("ALICE".to_string(), "BOB".to_string(), 10, Price::from(12))]);
+
+}
+
+#[test]
+fn test_transaction_market_sell() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(15));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::MARKET_SELL, 10, Price::from(0));
+
+    exchange.place_order(order1).unwrap();
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.transactions, vec![Transaction::new("BOB".to_string(), "ALICE".to_string(), 10, Price::from(15))]);
+
+}
+
+#[test]
+fn test_immediate_or_cancel() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(14));
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 15, Price::from(13));
+    order3.order.time_in_force = TimeInForce::ImmediateOrCancel;
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    let summary = exchange.place_order(order3).unwrap();
+
+    // Fills what crosses, never rests the rest
+    assert_eq!(summary.filled(), 10);
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::ImmediateOrCancel);
+    assert_eq!(summary.cancelled[0].order.amount, 5);
+
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert!(ledger.buy_orders().is_empty());
+    assert_eq!(ledger.sell_orders().len(), 1);
+
+}
+
+#[test]
+fn test_fill_or_kill() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(10));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    let before = exchange.query_ledger("CORN".to_string()).unwrap();
+
+    // Only 10 are bid at 11 or better
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 15, Price::from(11));
+    order3.order.time_in_force = TimeInForce::FillOrKill;
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert!(summary.transactions.is_empty());
+    assert!(summary.to_update.is_empty());
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::FillOrKill);
+    assert_eq!(summary.cancelled[0].order.amount, 15);
+
+    // The book is exactly as it was, apart from the arrival counter
+    let after = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert_eq!(before.buy_orders(), after.buy_orders());
+    assert_eq!(before.sell_orders(), after.sell_orders());
+
+    // Across both levels there is enough
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::MARKET_SELL, 15, Price::from(0));
+    order4.order.time_in_force = TimeInForce::FillOrKill;
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.filled(), 15);
+    assert!(summary.cancelled.is_empty());
+
+    // Nothing rests on a new item, so no book is opened for it
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "WHEAT".to_string(), OrderKind::BUY, 5, Price::from(11));
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "RYE".to_string(), OrderKind::SELL, 5, Price::from(11));
+    order5.order.time_in_force = TimeInForce::FillOrKill;
+    order6.order.time_in_force = TimeInForce::ImmediateOrCancel;
+    assert_eq!(exchange.place_order(order5).unwrap().cancelled[0].reason, CancelReason::FillOrKill);
+    assert_eq!(exchange.place_order(order6).unwrap().cancelled[0].reason, CancelReason::ImmediateOrCancel);
+    assert_eq!(exchange.query_ledger("WHEAT".to_string()), None);
+    assert_eq!(exchange.query_ledger("RYE".to_string()), None);
+
+}
+
+#[test]
+fn test_expire_orders() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    order1.order.expires_at = Some(5);
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(13));
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "WHEAT".to_string(), OrderKind::BUY, 10, Price::from(2));
+    order3.order.expires_at = Some(8);
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+
+    assert!(exchange.expire_orders(4).is_empty());
+
+    let expired = exchange.expire_orders(6);
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired["CORN"][0].price_per, Price::from(12));
+    assert_eq!(exchange.query_ledger("CORN".to_string()).unwrap().sell_orders().len(), 1);
+
+    // Time does not go backwards
+    exchange.expire_orders(2);
+    assert_eq!(exchange.now(), 6);
+
+    let mut order4 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    order4.order.expires_at = Some(6);
+    assert_eq!(exchange.place_order(order4), Err(MarketError::Expired));
+
+}
+
+#[test]
+fn test_matching_skips_expired() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    order1.order.expires_at = Some(5);
+    let order2 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Bob's order expires without anything sweeping the book
+    exchange.set_time(5);
+
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 15, Price::from(12));
+    order3.order.time_in_force = TimeInForce::FillOrKill;
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.cancelled[0].reason, CancelReason::FillOrKill);
+
+    let order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 5, Price::from(12));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.transactions[0].seller, "CAROL");
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Expired);
+    assert_eq!(summary.cancelled[0].order.user_id, "BOB");
+    assert_eq!(exchange.query_ledger("CORN".to_string()).unwrap().sell_orders().len(), 1);
+
+}
+
+#[test]
+fn test_default_lifetime() {
+
+    let mut exchange = Market::new();
+    exchange.set_time(5);
+    exchange.set_default_lifetime(Some(100));
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let mut order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    order2.order.expires_at = Some(20);
+
+    let summary = exchange.place_order(order1).unwrap();
+    assert_eq!(summary.created.unwrap().expires_at, Some(105));
+    let summary = exchange.place_order(order2).unwrap();
+    assert_eq!(summary.created.unwrap().expires_at, Some(20));
+
+    assert_eq!(exchange.expire_orders(105)["CORN"].len(), 2);
+
+}
+
+#[test]
+fn test_post_only_reject() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    order2.order.post_only = Some(PostOnly::Reject);
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(11));
+    order3.order.post_only = Some(PostOnly::Reject);
+
+    exchange.place_order(order1).unwrap();
+
+    let summary = exchange.place_order(order2).unwrap();
+    assert!(summary.transactions.is_empty());
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::PostOnly);
+
+    // Does not cross, so it rests as usual
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.created.unwrap().price_per, Price::from(11));
+
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+    order4.order.post_only = Some(PostOnly::Reject);
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(11));
+    order5.order.post_only = Some(PostOnly::Reject);
+    order5.order.time_in_force = TimeInForce::ImmediateOrCancel;
+
+    let error = MarketError::InvalidOrder("Only good-till-cancelled limit orders can be post-only".to_string());
+    assert_eq!(exchange.place_order(order4), Err(error.clone()));
+    assert_eq!(exchange.place_order(order5), Err(error));
+
+}
+
+#[test]
+fn test_post_only_reprice() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("CORN".to_string(), ItemSpec { tick_size: "0.25".parse().ok(), ..ItemSpec::default() }).unwrap();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(11));
+    order2.order.post_only = Some(PostOnly::Reprice);
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, "0.25".parse().unwrap());
+    order3.order.post_only = Some(PostOnly::Reprice);
+
+    exchange.place_order(order1).unwrap();
+
+    // Moved one tick above the best bid instead of trading
+    let summary = exchange.place_order(order2).unwrap();
+    assert!(summary.transactions.is_empty());
+    assert_eq!(summary.created.unwrap().price_per, "12.25".parse().unwrap());
+
+    // However far through the book it was priced
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.created.unwrap().price_per, "12.25".parse().unwrap());
+    assert_eq!(exchange.query_ledger("CORN".to_string()).unwrap().buy_orders()[0].amount, 10);
+
+}
+
+#[test]
+fn test_stop_sell() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(50));
+    let order2 = OrderRequest::new("DAVE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(45));
+    let mut order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    order3.order.trigger_price = Some(Price::from(49));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Waits in the trigger book, not on the ledger
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.created.unwrap().kind, OrderKind::STOP_SELL);
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert_eq!(ledger.stop_orders().len(), 1);
+    assert!(ledger.sell_orders().is_empty());
+
+    // A trade at 50 does not reach the trigger
+    let order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(50));
+    let summary = exchange.place_order(order4).unwrap();
+    assert!(summary.triggered.is_empty());
+
+    // A trade at 45 does
+    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 3, Price::from(45));
+    let summary = exchange.place_order(order5).unwrap();
+
+    println!("{:?}", summary);
+
+    let transactions_str = "[Transaction { buyer: \"DAVE\", seller: \"ALICE\", amount: 3, price_per: 45.00, buyer_fee: 0, seller_fee: 0 }, Transaction { buyer: \"DAVE\", seller: \"CAROL\", amount: 5, price_per: 45.00, buyer_fee: 0, seller_fee: 0 }]";
+    let triggered_str = "[Order { id: *, user_id: \"CAROL\", kind: STOP_SELL, amount: 5, *trigger_price: Some(49.00), * }]";
+
+    assert!(WildMatch::new(transactions_str).matches(format!("{:?}", summary.transactions).as_str()));
+    assert!(WildMatch::new(triggered_str).matches(format!("{:?}", summary.triggered).as_str()));
+    assert_eq!(summary.created, None);
+
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert!(ledger.stop_orders().is_empty());
+    assert_eq!(ledger.last_price(), Some(Price::from(45)));
+    assert_eq!(ledger.buy_orders()[0].amount, 2);
+
+}
+
+#[test]
+fn test_stop_cascade() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(12));
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(13));
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(14));
+    let mut order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_LIMIT_BUY, 8, Price::from(13));
+    order4.order.trigger_price = Some(Price::from(12));
+    let mut order5 = OrderRequest::new("DAN".to_string(), "CORN".to_string(), OrderKind::STOP_BUY, 3, Price::from(0));
+    order5.order.trigger_price = Some(Price::from(13));
+    let order6 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 5, Price::from(12));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+    exchange.place_order(order4).unwrap();
+    exchange.place_order(order5).unwrap();
+
+    // Alice trades at 12, setting off Carol, whose trade at 13 sets off Dan
+    let summary = exchange.place_order(order6).unwrap();
+
+    println!("{:?}", summary);
+
+    let transactions_str = "[Transaction { buyer: \"ALICE\", seller: \"BOB\", amount: 5, price_per: 12.00, buyer_fee: 0, seller_fee: 0 }, Transaction { buyer: \"CAROL\", seller: \"BOB\", amount: 5, price_per: 13.00, buyer_fee: 0, seller_fee: 0 }, Transaction { buyer: \"DAN\", seller: \"BOB\", amount: 3, price_per: 14.00, buyer_fee: 0, seller_fee: 0 }]";
+    assert!(WildMatch::new(transactions_str).matches(format!("{:?}", summary.transactions).as_str()));
+
+    let triggered: Vec<&str> = summary.triggered.iter().map(|x| x.user_id.as_str()).collect();
+    assert_eq!(triggered, vec!["CAROL", "DAN"]);
+
+    // The rest of Carol's stop-limit now rests as a limit order
+    let rested = summary.to_update.iter().find(|x| x.user_id == "CAROL").unwrap();
+    assert_eq!(rested.kind, OrderKind::BUY);
+    assert_eq!(rested.amount, 3);
+    assert_eq!(summary.created, None);
+
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert_eq!(ledger.buy_orders()[0].user_id, "CAROL");
+    assert_eq!(ledger.last_price(), Some(Price::from(14)));
+
+}
+
+#[test]
+fn test_stop_triggered_on_arrival() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 1, Price::from(10));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(10));
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(11));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+
+    // The last trade at 10 is already past a buy trigger of 8
+    let mut order4 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_BUY, 3, Price::from(0));
+    order4.order.trigger_price = Some(Price::from(8));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.created, None);
+    assert_eq!(summary.triggered[0].user_id, "CAROL");
+    assert_eq!(summary.filled(), 3);
+
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert!(ledger.stop_orders().is_empty());
+    assert_eq!(ledger.sell_orders()[0].amount, 2);
+
+}
+
+#[test]
+fn test_stop_triggers_follow_item_rules() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("ARROWS".to_string(), ItemSpec {
+        tick_size: Some("0.05".parse().unwrap()),
+        max_price: Some(Price::from(50)),
+        ..ItemSpec::default()
+    }).unwrap();
+
+    // Triggers are rounded to the item's decimals, so they fire no sooner than asked
+    let mut order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::STOP_LIMIT_SELL, 5, Price::from(10));
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::STOP_BUY, 5, Price::from(0));
+    order1.order.trigger_price = Some("10.0001".parse().unwrap());
+    order2.order.trigger_price = Some("10.001".parse().unwrap());
+    assert_eq!(exchange.place_order(order1).unwrap().created.unwrap().trigger_price.unwrap().to_string(), "10.00");
+    assert_eq!(exchange.place_order(order2).unwrap().created.unwrap().trigger_price.unwrap().to_string(), "10.01");
+
+    // And so are a bracket's exits, like orders on the other side
+    let mut order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 5, Price::from(9));
+    order3.order.bracket = Some(Bracket { take_profit: "12.001".parse().unwrap(), stop_loss: "7.999".parse().unwrap() });
+    let bracket = exchange.place_order(order3).unwrap().created.unwrap().bracket.unwrap();
+    assert_eq!((bracket.take_profit.to_string(), bracket.stop_loss.to_string()), ("12.01".to_string(), "7.99".to_string()));
+
+    // Then checked against the tick size and price bounds
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::STOP_BUY, 5, Price::from(0));
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::BUY, 5, Price::from(10));
+    order4.order.trigger_price = Some("2.01".parse().unwrap());
+    order5.order.trigger_price = Some(Price::from(60));
+    order6.order.bracket = Some(Bracket { take_profit: Price::from(20), stop_loss: "5.02".parse().unwrap() });
+    assert_eq!(exchange.place_order(order4), Err(MarketError::InvalidPrice("Trigger price must be a multiple of the tick size 0.05".to_string())));
+    assert_eq!(exchange.place_order(order5), Err(MarketError::InvalidPrice("Trigger price must be at most 50".to_string())));
+    assert_eq!(exchange.place_order(order6), Err(MarketError::InvalidPrice("Trigger price must be a multiple of the tick size 0.05".to_string())));
+
+}
+
+#[test]
+fn test_stop_orders_cancel_and_reload() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_LIMIT_SELL, 5, Price::from(9));
+    order1.order.trigger_price = Some(Price::from(10));
+    let mut order2 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    order2.order.trigger_price = Some(Price::from(11));
+
+    let stop = exchange.place_order(order1).unwrap().created.unwrap();
+    exchange.place_order(order2).unwrap();
+
+    let exchange_data = exchange.to_json();
+    let mut reloaded = Market::from_json(exchange_data);
+    assert_eq!(reloaded.query_ledger("CORN".to_string()).unwrap().stop_orders().len(), 2);
+
+    assert_eq!(exchange.cancel(stop.id).unwrap()[0].kind, OrderKind::STOP_LIMIT_SELL);
+    assert_eq!(exchange.query_ledger("CORN".to_string()).unwrap().stop_orders().len(), 1);
+    assert_eq!(reloaded.cancel(stop.id).unwrap()[0].trigger_price, Some(Price::from(10)));
+
+    let no_trigger = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    let mut stray_trigger = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(9));
+    stray_trigger.order.trigger_price = Some(Price::from(10));
+
+    assert_eq!(exchange.place_order(no_trigger), Err(MarketError::InvalidOrder("Stop orders need a trigger price".to_string())));
+    assert_eq!(exchange.place_order(stray_trigger), Err(MarketError::InvalidOrder("Only stop orders take a trigger price".to_string())));
+
+}
+
+#[test]
+fn test_trailing_stop_offset() {
+
+    let mut exchange = Market::new();
+
+    let trade_at = |exchange: &mut Market, price: i64| {
+        let sell = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 1, Price::from(price));
+        let buy = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(price));
+        exchange.place_order(sell).unwrap();
+        exchange.place_order(buy).unwrap()
+    };
+    let trigger = |exchange: &mut Market| {
+        exchange.query_ledger("CORN".to_string()).unwrap().stop_orders()[0].trigger_price
+    };
+
+    let mut order1 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    order1.order.trail = Some(Trail::Offset(Price::from(5)));
+    let mut order2 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    order2.order.trail = Some(Trail::Offset(Price::from(5)));
+
+    assert_eq!(exchange.place_order(order1), Err(MarketError::InvalidOrder("Trailing stops need a trigger price until the item has traded".to_string())));
+
+    trade_at(&mut exchange, 50);
+    exchange.place_order(order2).unwrap();
+    assert_eq!(trigger(&mut exchange), Some(Price::from(45)));
+
+    // Follows the price up, never back down
+    trade_at(&mut exchange, 55);
+    assert_eq!(trigger(&mut exchange), Some(Price::from(50)));
+    trade_at(&mut exchange, 52);
+    assert_eq!(trigger(&mut exchange), Some(Price::from(50)));
+
+    let order3 = OrderRequest::new("DAVE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(40));
+    exchange.place_order(order3).unwrap();
+
+    let summary = trade_at(&mut exchange, 50);
+    assert_eq!(summary.triggered.len(), 1);
+    assert_eq!(summary.transactions[1], Transaction::new("DAVE".to_string(), "CAROL".to_string(), 5, Price::from(40)));
+
+}
+
+#[test]
+fn test_trailing_stop_follows_each_fill() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 1, Price::from(95));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(95));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    let mut order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_SELL, 5, Price::from(0));
+    order3.order.trail = Some(Trail::Offset(Price::from(5)));
+    let stop = exchange.place_order(order3).unwrap().created.unwrap();
+    assert_eq!(stop.trigger_price, Some(Price::from(90)));
+
+    let order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 2, Price::from(100));
+    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(94));
+    exchange.place_order(order4).unwrap();
+    exchange.place_order(order5).unwrap();
+
+    // The fill at 100 lifts the trigger to 95, and the fill at 94 then reaches it
+    let order6 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::MARKET_SELL, 3, Price::from(0));
+    let summary = exchange.place_order(order6).unwrap();
+
+    assert_eq!(summary.triggered.len(), 1);
+    assert_eq!(summary.triggered[0].trigger_price, Some(Price::from(95)));
+    assert_eq!(summary.filled(), 8);
+
+}
+
+#[test]
+fn test_trailing_stop_percent() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 1, Price::from(100));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(100));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Given a looser trigger than the trail allows, it starts at the trail
+    let mut order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_BUY, 5, Price::from(0));
+    order3.order.trail = Some(Trail::Percent(Price::from(10)));
+    order3.order.trigger_price = Some(Price::from(150));
+    let stop = exchange.place_order(order3).unwrap().created.unwrap();
+    assert_eq!(stop.trigger_price, Some(Price::from(110)));
+
+    // Follows a falling price down
+    let order4 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(90));
+    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 1, Price::from(90));
+    exchange.place_order(order4).unwrap();
+    let summary = exchange.place_order(order5).unwrap();
+    assert!(summary.triggered.is_empty());
+
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    assert_eq!(ledger.stop_orders()[0].trigger_price, Some(Price::from(99)));
+
+    // Cancelling still finds it after the move
+    assert_eq!(exchange.cancel(stop.id).unwrap()[0].user_id, "CAROL");
+
+    let mut limit = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_LIMIT_BUY, 5, Price::from(120));
+    limit.order.trail = Some(Trail::Percent(Price::from(10)));
+    assert_eq!(exchange.place_order(limit), Err(MarketError::InvalidOrder("Only STOP_BUY and STOP_SELL orders can trail".to_string())));
+
+    let mut too_far = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::STOP_BUY, 5, Price::from(0));
+    too_far.order.trail = Some(Trail::Percent(Price::from(100)));
+    assert_eq!(exchange.place_order(too_far), Err(MarketError::InvalidPrice("Trailing percentage must be between 0 and 100".to_string())));
+
+}
+
+#[test]
+fn test_iceberg() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("GUILD".to_string(), "CORN".to_string(), OrderKind::BUY, 250, Price::from(12));
+    order1.order.display = Some(100);
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 50, Price::from(12));
+
+    let iceberg = exchange.place_order(order1).unwrap().created.unwrap();
+    assert_eq!((iceberg.amount, iceberg.reserve), (100, 150));
+    exchange.place_order(order2).unwrap();
+
+    // Only the slice is public
+    let ledger = exchange.query_ledger("CORN".to_string()).unwrap();
+    let public = serde_json::to_string(&ledger.to_public_json()).unwrap();
+    assert!(!public.contains("reserve"));
+    assert!(public.contains("\"amount\":100"));
+
+    // The slice fills, the next one goes behind Bob
+    let order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 130, Price::from(12));
+    let summary = exchange.place_order(order3).unwrap();
+
+    let buyers: Vec<(&str, u32)> = summary.transactions.iter().map(|x| (x.buyer.as_str(), x.amount)).collect();
+    assert_eq!(buyers, vec![("GUILD", 100), ("BOB", 30)]);
+    assert_eq!(summary.hidden_filled, 0);
+
+    let buy_orders = exchange.query_ledger("CORN".to_string()).unwrap().buy_orders();
+    let buyers: Vec<(&str, u32, u32)> = buy_orders.iter().map(|x| (x.user_id.as_str(), x.amount, x.reserve)).collect();
+    assert_eq!(buyers, vec![("BOB", 20, 0), ("GUILD", 100, 50)]);
+
+    // Taking more than is on show reaches into the reserve
+    let order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 200, Price::from(12));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.filled(), 170);
+    assert_eq!(summary.visible_filled(), 120);
+    assert_eq!(summary.hidden_filled, 50);
+    assert_eq!(summary.created.unwrap().amount, 30);
+    assert!(exchange.query_ledger("CORN".to_string()).unwrap().buy_orders().is_empty());
+
+}
+
+#[test]
+fn test_iceberg_rules() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("ARROWS".to_string(), ItemSpec { lot_size: 10, ..ItemSpec::default() }).unwrap();
+
+    let mut order1 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(1));
+    order1.order.display = Some(15);
+    let mut order2 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(1));
+    order2.order.display = Some(20);
+    order2.order.time_in_force = TimeInForce::ImmediateOrCancel;
+    let mut order3 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(1));
+    order3.order.display = Some(20);
+
+    assert_eq!(exchange.place_order(order1), Err(MarketError::InvalidQuantity("Display quantity must be a multiple of the lot size 10".to_string())));
+    assert_eq!(exchange.place_order(order2), Err(MarketError::InvalidOrder("Only good-till-cancelled limit orders can be icebergs".to_string())));
+
+    // A fill-or-kill counts the reserve, and a reload keeps it
+    exchange.place_order(order3).unwrap();
+    let mut reloaded = Market::from_json(exchange.to_json());
+
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::SELL, 100, Price::from(1));
+    order4.order.time_in_force = TimeInForce::FillOrKill;
+    assert_eq!(reloaded.place_order(order4).unwrap().filled(), 100);
+
+}
+
+#[test]
+fn test_all_or_none() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(2));
+    order1.order.all_or_none = true;
+    let order2 = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::BUY, 30, Price::from(2));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Too small for the bundle: the guild is passed over but keeps its place
+    let order3 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::SELL, 20, Price::from(2));
+    let summary = exchange.place_order(order3).unwrap();
+
+    let buyers: Vec<(&str, u32)> = summary.transactions.iter().map(|x| (x.buyer.as_str(), x.amount)).collect();
+    assert_eq!(buyers, vec![("BOB", 20)]);
+
+    let buy_orders = exchange.query_ledger("ARROWS".to_string()).unwrap().buy_orders();
+    let buyers: Vec<&str> = buy_orders.iter().map(|x| x.user_id.as_str()).collect();
+    assert_eq!(buyers, vec!["GUILD", "BOB"]);
+
+    // Big enough: the guild still comes first
+    let order4 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::SELL, 105, Price::from(2));
+    let summary = exchange.place_order(order4).unwrap();
+
+    let buyers: Vec<(&str, u32)> = summary.transactions.iter().map(|x| (x.buyer.as_str(), x.amount)).collect();
+    assert_eq!(buyers, vec![("GUILD", 100), ("BOB", 5)]);
+
+    // An incoming all-or-none order that cannot fill in full rests untouched
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::SELL, 10, Price::from(2));
+    order5.order.all_or_none = true;
+    let summary = exchange.place_order(order5).unwrap();
+
+    assert!(summary.transactions.is_empty());
+    assert!(summary.created.unwrap().all_or_none);
+
+}
+
+#[test]
+fn test_min_fill() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::SELL, 100, Price::from(2));
+    order1.order.min_fill = Some(40);
+    let order2 = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::SELL, 25, Price::from(3));
+
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // A trade smaller than the minimum skips the guild for the next price
+    let order3 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::BUY, 10, Price::from(3));
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.transactions, vec![Transaction::new("ALICE".to_string(), "BOB".to_string(), 10, Price::from(3))]);
+
+    let order4 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::BUY, 70, Price::from(2));
+    let summary = exchange.place_order(order4).unwrap();
+    assert_eq!(summary.transactions, vec![Transaction::new("ALICE".to_string(), "GUILD".to_string(), 70, Price::from(2))]);
+
+    // Less than the minimum is left, so the rest may go in one trade of any size
+    let order5 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::BUY, 30, Price::from(2));
+    assert_eq!(exchange.place_order(order5).unwrap().filled(), 30);
+
+    // An incoming order wants its minimum in total, or no trade at all
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 50, Price::from(0));
+    order6.order.min_fill = Some(20);
+    let summary = exchange.place_order(order6).unwrap();
+
+    assert!(summary.transactions.is_empty());
+    assert_eq!(summary.cancelled[0].reason, CancelReason::NoLiquidity);
+    assert_eq!(summary.cancelled[0].order.amount, 50);
+
+    let mut order7 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 50, Price::from(0));
+    order7.order.min_fill = Some(15);
+    let summary = exchange.place_order(order7).unwrap();
+
+    assert_eq!(summary.filled(), 15);
+    assert_eq!(summary.cancelled[0].order.amount, 35);
+
+}
+
+#[test]
+fn test_all_or_none_rules() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(2));
+    order1.order.min_fill = Some(0);
+    let mut order2 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(2));
+    order2.order.display = Some(10);
+    order2.order.all_or_none = true;
+
+    assert_eq!(exchange.place_order(order1), Err(MarketError::InvalidQuantity("Minimum fill must be at least 1".to_string())));
+    assert_eq!(exchange.place_order(order2), Err(MarketError::InvalidOrder("Icebergs cannot be all-or-none or have a minimum fill".to_string())));
+
+    // Fill-or-kill passes over bundles too big for it
+    let mut order3 = OrderRequest::new("GUILD".to_string(), "ARROWS".to_string(), OrderKind::BUY, 100, Price::from(2));
+    order3.order.all_or_none = true;
+    exchange.place_order(order3).unwrap();
+
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::SELL, 50, Price::from(2));
+    order4.order.time_in_force = TimeInForce::FillOrKill;
+    let summary = exchange.place_order(order4).unwrap();
+    assert_eq!(summary.cancelled[0].reason, CancelReason::FillOrKill);
+
+    // And the flag survives a reload
+    let mut reloaded = Market::from_json(exchange.to_json());
+    let buy_orders = reloaded.query_ledger("ARROWS".to_string()).unwrap().buy_orders();
+    assert!(buy_orders[0].all_or_none);
+
+}
+
+#[test]
+fn test_one_cancels_other() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::BUY, 10, Price::from(100));
+    let order2 = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(100));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Sell at 120 or stop out at 80
+    let take_profit = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(120));
+    let mut stop_loss = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::STOP_SELL, 10, Price::from(0));
+    stop_loss.order.trigger_price = Some(Price::from(80));
+
+    let summary = exchange.place_oco(take_profit, stop_loss).unwrap();
+    let first = summary.created.unwrap();
+    assert_eq!(summary.linked.len(), 1);
+    assert_eq!(summary.to_update, summary.linked);
+    assert_eq!(first.oco, Some(summary.linked[0].id));
+    assert_eq!(summary.linked[0].oco, Some(first.id));
+
+    // Filling the take profit cancels the stop
+    let order3 = OrderRequest::new("CAROL".to_string(), "SWORD".to_string(), OrderKind::BUY, 4, Price::from(120));
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert_eq!(summary.filled(), 4);
+    assert_eq!(summary.cancelled.len(), 1);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Linked);
+    assert_eq!(summary.cancelled[0].order.kind, OrderKind::STOP_SELL);
+
+    let ledger = exchange.query_ledger("SWORD".to_string()).unwrap();
+    assert!(ledger.stop_orders().is_empty());
+    assert_eq!(ledger.sell_orders()[0].amount, 6);
+
+    // Cancelling a leg takes the other with it
+    let take_profit = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::BUY, 5, Price::from(90));
+    let mut stop_loss = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::STOP_BUY, 5, Price::from(0));
+    stop_loss.order.trigger_price = Some(Price::from(130));
+
+    let first = exchange.place_oco(take_profit, stop_loss).unwrap().created.unwrap();
+    let cancelled = exchange.cancel(first.id).unwrap();
+    assert_eq!(cancelled.len(), 2);
+    assert_eq!(cancelled[0].oco, Some(cancelled[1].id));
+    assert_eq!(cancelled[1].kind, OrderKind::STOP_BUY);
+    assert!(exchange.query_ledger("SWORD".to_string()).unwrap().stop_orders().is_empty());
+
+}
+
+#[test]
+fn test_one_cancels_other_rules() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(120));
+    let order2 = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(80));
+    assert_eq!(exchange.place_oco(order1, order2), Err(MarketError::InvalidOrder("Both legs of a one-cancels-other pair must be for the same item and user".to_string())));
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(120));
+    let order2 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::MARKET_SELL, 10, Price::from(0));
+    assert_eq!(exchange.place_oco(order1, order2), Err(MarketError::InvalidOrder("One-cancels-other legs must be good-till-cancelled limit or stop orders".to_string())));
+
+    // A first leg that trades on arrival leaves the second unplaced
+    let order3 = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::BUY, 10, Price::from(130));
+    exchange.place_order(order3).unwrap();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(120));
+    let order2 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(150));
+    let summary = exchange.place_oco(order1, order2).unwrap();
+
+    assert_eq!(summary.filled(), 10);
+    assert!(summary.linked.is_empty());
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Linked);
+    assert_eq!(summary.cancelled[0].order.price_per, Price::from(150));
+    assert!(exchange.query_ledger("SWORD".to_string()).unwrap().sell_orders().is_empty());
+
+    // So does a stop leg that fires on arrival, even without trading
+    let mut order1 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::STOP_SELL, 10, Price::from(0));
+    order1.order.trigger_price = Some(Price::from(140));
+    let order2 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(150));
+    let summary = exchange.place_oco(order1, order2).unwrap();
+
+    assert_eq!(summary.cancelled[0].reason, CancelReason::NoLiquidity);
+    assert_eq!(summary.cancelled[1].reason, CancelReason::Linked);
+    assert!(summary.linked.is_empty());
+    assert!(exchange.query_ledger("SWORD".to_string()).unwrap().sell_orders().is_empty());
+
+    // Expiry takes both legs
+    let mut order1 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(120));
+    order1.order.expires_at = Some(10);
+    let order2 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(150));
+    exchange.place_oco(order1, order2).unwrap();
+
+    let expired = exchange.expire_orders(10);
+    assert_eq!(expired["SWORD"].len(), 2);
+
+}
+
+#[test]
+fn test_bracket() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::SELL, 10, Price::from(100));
+    exchange.place_order(order1).unwrap();
+
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::BUY, 15, Price::from(100));
+    order2.order.bracket = Some(Bracket { take_profit: Price::from(120), stop_loss: Price::from(80) });
+    let summary = exchange.place_order(order2).unwrap();
+
+    // Exits cover only what the entry bought
+    assert_eq!(summary.filled(), 10);
+    assert_eq!(summary.created.unwrap().amount, 5);
+
+    let exits: Vec<(OrderKind, u32)> = summary.linked.iter().map(|x| (x.kind, x.amount)).collect();
+    assert_eq!(exits, vec![(OrderKind::SELL, 10), (OrderKind::STOP_SELL, 10)]);
+    assert_eq!(summary.to_update.len(), 3);
+
+    // The rest of the entry trades later and brings its own exits
+    let order3 = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::SELL, 5, Price::from(100));
+    let summary = exchange.place_order(order3).unwrap();
+    assert_eq!(summary.linked.len(), 2);
+
+    // The price falls: the stops fire and cancel their take profits
+    let order4 = OrderRequest::new("CAROL".to_string(), "SWORD".to_string(), OrderKind::BUY, 20, Price::from(75));
+    let order5 = OrderRequest::new("BOB".to_string(), "SWORD".to_string(), OrderKind::SELL, 1, Price::from(75));
+    exchange.place_order(order4).unwrap();
+    let summary = exchange.place_order(order5).unwrap();
+
+    assert_eq!(summary.triggered.len(), 2);
+    assert_eq!(summary.filled(), 16);
+    let reasons: Vec<CancelReason> = summary.cancelled.iter().map(|x| x.reason).collect();
+    assert_eq!(reasons, vec![CancelReason::Linked, CancelReason::Linked]);
+    assert!(exchange.query_ledger("SWORD".to_string()).unwrap().sell_orders().is_empty());
+
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "SWORD".to_string(), OrderKind::BUY, 15, Price::from(100));
+    order6.order.bracket = Some(Bracket { take_profit: Price::from(80), stop_loss: Price::from(120) });
+    assert_eq!(exchange.place_order(order6), Err(MarketError::InvalidOrder("A bracket's stop loss must be on the losing side of its take profit".to_string())));
+
+}
+
+#[test]
+fn test_amend_order() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 20, Price::from(10));
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 20, Price::from(10));
+    let alice = exchange.place_order(order1).unwrap().created.unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Less at the same price keeps Alice first
+    let summary = exchange.amend_order(alice.id, 15, Price::from(10)).unwrap();
+    assert_eq!(summary.to_update[0].amount, 15);
+
+    let buy_orders = exchange.query_ledger("CORN".to_string()).unwrap().buy_orders();
+    let buyers: Vec<(&str, u32)> = buy_orders.iter().map(|x| (x.user_id.as_str(), x.amount)).collect();
+    assert_eq!(buyers, vec![("ALICE", 15), ("BOB", 20)]);
+
+    // More sends her to the back, under the same id
+    let summary = exchange.amend_order(alice.id, 25, Price::from(10)).unwrap();
+    assert_eq!(summary.created.unwrap().id, alice.id);
+
+    let buy_orders = exchange.query_ledger("CORN".to_string()).unwrap().buy_orders();
+    let buyers: Vec<(&str, u32)> = buy_orders.iter().map(|x| (x.user_id.as_str(), x.amount)).collect();
+    assert_eq!(buyers, vec![("BOB", 20), ("ALICE", 25)]);
+
+    // A price that crosses the book trades
+    let order3 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(12));
+    exchange.place_order(order3).unwrap();
+
+    let summary = exchange.amend_order(alice.id, 25, Price::from(12)).unwrap();
+    assert_eq!(summary.transactions, vec![Transaction::new("ALICE".to_string(), "CAROL".to_string(), 10, Price::from(12))]);
+    assert_eq!(summary.created.unwrap().amount, 15);
+
+    assert_eq!(exchange.amend_order(alice.id, 0, Price::from(12)), Err(MarketError::InvalidQuantity("Amount must be at least 1".to_string())));
+    assert_eq!(exchange.amend_order(Uuid::new_v4(), 10, Price::from(12)), Err(MarketError::OrderNotFound));
+
+}
+
+#[test]
+fn test_amend_iceberg() {
+
+    let mut exchange = Market::new();
+
+    let mut order1 = OrderRequest::new("GUILD".to_string(), "CORN".to_string(), OrderKind::SELL, 250, Price::from(12));
+    order1.order.display = Some(100);
+    let iceberg = exchange.place_order(order1).unwrap().created.unwrap();
+
+    // The reserve goes first
+    let summary = exchange.amend_order(iceberg.id, 120, Price::from(12)).unwrap();
+    assert_eq!((summary.to_update[0].amount, summary.to_update[0].reserve), (100, 20));
+
+    let summary = exchange.amend_order(iceberg.id, 60, Price::from(12)).unwrap();
+    assert_eq!((summary.to_update[0].amount, summary.to_update[0].reserve), (60, 0));
+
+    let summary = exchange.amend_order(iceberg.id, 300, Price::from(13)).unwrap();
+    let created = summary.created.unwrap();
+    assert_eq!((created.amount, created.reserve), (100, 200));
+
+}
+
+#[test]
+fn test_market_order_slippage() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 5, Price::from(10));
+    let order2 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 5, Price::new(1050, 2));
+    let order3 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 5, Price::from(1000));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+    exchange.place_order(order3).unwrap();
+
+    // 5% over the best price reaches 10.50 but not 1000
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 20, Price::from(0));
+    order4.order.slippage = Some(Slippage::Percent(Price::from(5)));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.filled(), 10);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Slippage);
+    assert_eq!(summary.cancelled[0].order.amount, 10);
+
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 5, Price::from(0));
+    order5.order.slippage = Some(Slippage::WorstPrice(Price::from(999)));
+    let summary = exchange.place_order(order5).unwrap();
+    assert_eq!(summary.filled(), 0);
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Slippage);
+
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 5, Price::from(0));
+    order6.order.slippage = Some(Slippage::Offset(Price::from(1000)));
+    assert_eq!(exchange.place_order(order6).unwrap().filled(), 5);
+
+    // Without more on the book it is still a lack of liquidity
+    let mut order7 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 5, Price::from(0));
+    order7.order.slippage = Some(Slippage::Offset(Price::from(1)));
+    assert_eq!(exchange.place_order(order7).unwrap().cancelled[0].reason, CancelReason::NoLiquidity);
+
+    let mut order8 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 5, Price::from(10));
+    order8.order.slippage = Some(Slippage::Offset(Price::from(1)));
+    assert_eq!(exchange.place_order(order8), Err(MarketError::InvalidOrder("Only market and stop orders take a slippage limit".to_string())));
+
+    let mut order9 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_SELL, 5, Price::from(0));
+    order9.order.slippage = Some(Slippage::Percent(Price::from(0)));
+    assert_eq!(exchange.place_order(order9), Err(MarketError::InvalidPrice("Slippage limit must be positive".to_string())));
+
+}
+
+#[test]
+fn test_market_buy_by_budget() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 5, Price::from(100));
+    let order2 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::SELL, 10, Price::from(150));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // As many potions as 1000 gold gets: 5 at 100, then 3 at 150
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order3.order.budget = Some(Price::from(1000));
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert_eq!(summary.filled(), 8);
+    assert_eq!(summary.spent, Some(Price::from(950)));
+    assert_eq!(summary.budget_left, Some(Price::from(50)));
+    assert!(summary.cancelled.is_empty());
+
+    // An amount caps the budget
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 2, Price::from(0));
+    order4.order.budget = Some(Price::from(1000));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.filled(), 2);
+    assert_eq!(summary.budget_left, Some(Price::from(700)));
+
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::BUY, 0, Price::from(100));
+    order5.order.budget = Some(Price::from(1000));
+    assert_eq!(exchange.place_order(order5), Err(MarketError::InvalidOrder("Only market buys take a budget".to_string())));
+
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order6.order.budget = Some(Price::from(0));
+    assert_eq!(exchange.place_order(order6), Err(MarketError::InvalidPrice("Budget must be positive".to_string())));
+
+}
+
+#[test]
+fn test_market_buy_by_budget_with_fees() {
+
+    let mut exchange = Market::new();
+    exchange.set_fees(Some(FeeSchedule { taker_bps: 1000, ..FeeSchedule::default() })).unwrap();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "POTION".to_string(), OrderKind::SELL, 5, Price::from(100));
+    let order2 = OrderRequest::new("CAROL".to_string(), "POTION".to_string(), OrderKind::SELL, 10, Price::from(150));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // The taker fee comes out of the budget too: 5 at 110, then 2 at 165
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "POTION".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order3.order.budget = Some(Price::from(1000));
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert_eq!(summary.filled(), 7);
+    assert_eq!(summary.fees(), Price::from(80));
+    assert_eq!(summary.spent, Some(Price::from(880)));
+    assert_eq!(summary.budget_left, Some(Price::from(120)));
+
+}
+
+#[test]
+fn test_market_buy_by_budget_in_lots() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("ARROWS".to_string(), ItemSpec { lot_size: 10, ..ItemSpec::default() }).unwrap();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::SELL, 100, Price::from(1));
+    exchange.place_order(order1).unwrap();
+
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order2.order.budget = Some(Price::from(45));
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.filled(), 40);
+    assert_eq!(summary.budget_left, Some(Price::from(5)));
+
+    // Too little for a single lot
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order3.order.budget = Some(Price::from(5));
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert!(summary.transactions.is_empty());
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Budget);
+    assert_eq!(summary.budget_left, Some(Price::from(5)));
+
+    // Nothing left to buy at all
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order4.order.budget = Some(Price::from(500));
+    exchange.place_order(order4).unwrap();
+
+    let mut order5 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+    order5.order.budget = Some(Price::from(500));
+    let summary = exchange.place_order(order5).unwrap();
+
+    assert_eq!(summary.cancelled[0].reason, CancelReason::NoLiquidity);
+    assert_eq!(summary.cancelled[0].order.amount, 10);
+
+}
+
+#[test]
+fn test_market_buy_by_budget_below_minimum() {
+
+    let mut exchange = Market::new();
+    exchange.register_item("ARROWS".to_string(), ItemSpec { min_quantity: 10, ..ItemSpec::default() }).unwrap();
+
+    let order1 = OrderRequest::new("BOB".to_string(), "ARROWS".to_string(), OrderKind::SELL, 100, Price::from(10));
+    exchange.place_order(order1).unwrap();
+
+    // 30 buys 3 arrows, fewer than may trade
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order2.order.budget = Some(Price::from(30));
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert!(summary.transactions.is_empty());
+    assert_eq!(summary.cancelled[0].reason, CancelReason::Budget);
+    assert_eq!(summary.budget_left, Some(Price::from(30)));
+
+    let mut order3 = OrderRequest::new("ALICE".to_string(), "ARROWS".to_string(), OrderKind::MARKET_BUY, 0, Price::from(0));
+    order3.order.budget = Some(Price::from(100));
+    assert_eq!(exchange.place_order(order3).unwrap().filled(), 10);
+
+}
+
+#[test]
+fn test_self_trade_prevention() {
+
+    // Off by default: a user can still trade with themselves
+    let mut exchange = Market::new();
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(5));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(5));
+    exchange.place_order(order1).unwrap();
+    assert_eq!(exchange.place_order(order2).unwrap().filled(), 10);
+
+    let modes = [
+        (SelfTrade::CancelNewest, 0, vec![("ALICE", 12)], 10),
+        (SelfTrade::CancelOldest, 6, vec![("ALICE", 10)], 0),
+        (SelfTrade::CancelBoth, 0, vec![("ALICE", 10), ("ALICE", 12)], 0),
+        (SelfTrade::DecrementAndCancel, 2, vec![("ALICE", 10)], 0),
+    ];
+
+    for (mode, filled, cancelled, resting) in modes {
+        let mut exchange = Market::new();
+        exchange.set_self_trade_prevention(Some(mode));
+
+        let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(5));
+        let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 6, Price::from(5));
+        let order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 12, Price::from(5));
+        exchange.place_order(order1).unwrap();
+        exchange.place_order(order2).unwrap();
+        let summary = exchange.place_order(order3).unwrap();
+
+        assert_eq!(summary.filled(), filled, "{:?}", mode);
+        assert!(summary.transactions.iter().all(|x| x.buyer != x.seller));
+        assert!(summary.cancelled.iter().all(|x| x.reason == CancelReason::SelfTrade));
+        let cancelled_orders: Vec<(&str, u32)> = summary.cancelled.iter().map(|x| (x.order.user_id.as_str(), x.order.amount)).collect();
+        assert_eq!(cancelled_orders, cancelled, "{:?}", mode);
+
+        let sell_orders = exchange.query_ledger("CORN".to_string()).unwrap().sell_orders();
+        let alice: u32 = sell_orders.iter().filter(|x| x.user_id == "ALICE").map(|x| x.amount).sum();
+        assert_eq!(alice, resting, "{:?}", mode);
+    }
+
+}
+
+#[test]
+fn test_self_trade_decrement() {
+
+    let mut exchange = Market::new();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 10, Price::from(5));
+    exchange.place_order(order1).unwrap();
+
+    // The smaller incoming order runs out; the resting one is cut down
+    let mut order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 4, Price::from(5));
+    order2.order.self_trade = Some(SelfTrade::DecrementAndCancel);
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert!(summary.transactions.is_empty());
+    assert!(summary.created.is_none());
+    assert_eq!(summary.to_update[0].amount, 6);
+    assert_eq!(summary.cancelled[0].order.amount, 4);
+
+    // A fill-or-kill does not count its owner's orders
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(5));
+    exchange.place_order(order3).unwrap();
+
+    let mut order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 8, Price::from(5));
+    order4.order.self_trade = Some(SelfTrade::CancelOldest);
+    order4.order.time_in_force = TimeInForce::FillOrKill;
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.cancelled[0].reason, CancelReason::FillOrKill);
+    assert_eq!(exchange.query_ledger("CORN".to_string()).unwrap().sell_orders().len(), 2);
+
+}
+
+#[test]
+fn test_fees() {
+
+    let mut exchange = Market::new();
+    exchange.set_fees(Some(FeeSchedule { maker_bps: 10, taker_bps: 25, ..FeeSchedule::default() })).unwrap();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 20, Price::from(12));
+    exchange.place_order(order1).unwrap();
+
+    // The incoming buyer is the taker
+    let order2 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(12));
+    let summary = exchange.place_order(order2).unwrap();
+
+    assert_eq!(summary.transactions[0].buyer_fee, "0.30".parse().unwrap());
+    assert_eq!(summary.transactions[0].seller_fee, "0.12".parse().unwrap());
+    assert_eq!(summary.fees(), "0.42".parse().unwrap());
+    assert_eq!(summary.rebates(), Price::from(0));
+
+    // An item's own schedule takes over, here with a minimum fee and a maker rebate
+    let fees = FeeSchedule { taker_bps: 25, min_fee: Some(Price::from(1)), maker_rebate_bps: Some(5), ..FeeSchedule::default() };
+    exchange.register_item("CORN".to_string(), ItemSpec { fees: Some(fees), ..ItemSpec::default() }).unwrap();
+
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(12));
+    let summary = exchange.place_order(order3).unwrap();
+
+    assert_eq!(summary.transactions[0].buyer_fee.to_string(), "1.00");
+    assert_eq!(summary.transactions[0].seller_fee, Price::from(0));
+
+    let order4 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::BUY, 9, Price::from(12));
+    let summary = exchange.place_order(order4).unwrap();
+
+    assert_eq!(summary.transactions[0].buyer_fee, Price::from(1));
+    assert_eq!(summary.transactions[0].seller_fee.to_string(), "-0.05");
+    assert_eq!(summary.rebates(), "0.05".parse().unwrap());
+
+    // No schedule, no fees
+    exchange.register_item("CORN".to_string(), ItemSpec::default()).unwrap();
+    exchange.set_fees(None).unwrap();
+
+    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 1, Price::from(12));
+    let order6 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 1, Price::from(0));
+    exchange.place_order(order5).unwrap();
+    let summary = exchange.place_order(order6).unwrap();
+    assert_eq!(summary.fees(), Price::from(0));
+
+}
+
+#[test]
+fn test_bad_fee_schedules() {
+
+    let mut exchange = Market::new();
+
+    let too_high = FeeSchedule { taker_bps: 10_001, ..FeeSchedule::default() };
+    let rebate = FeeSchedule { taker_bps: 5, maker_rebate_bps: Some(10), ..FeeSchedule::default() };
+    let negative = FeeSchedule { min_fee: Some(Price::from(-1)), ..FeeSchedule::default() };
+
+    assert_eq!(exchange.set_fees(Some(too_high)), Err(MarketError::InvalidSpec("Fees must be at most 10000 basis points".to_string())));
+    assert_eq!(exchange.set_fees(Some(rebate)), Err(MarketError::InvalidSpec("Maker rebate cannot be more than the taker fee".to_string())));
+    assert_eq!(exchange.register_item("CORN".to_string(), ItemSpec { fees: Some(negative), ..ItemSpec::default() }), Err(MarketError::InvalidSpec("Minimum fee cannot be negative".to_string())));
+
+}
+
+#[test]
+fn test_risk_limits() {
+
+    let mut exchange = Market::new();
+    exchange.set_risk_limits(RiskLimits { max_open_orders: Some(2), max_notional: Some(Price::from(1000)), max_open_quantity: Some(50) }).unwrap();
+
+    // Worth too much
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 20, Price::from(60));
+    assert_eq!(exchange.place_order(order1), Err(MarketError::LimitExceeded("Order is worth more than the limit of 1000".to_string())));
+
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 30, Price::from(10));
+    let order3 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 21, Price::from(20));
+    exchange.place_order(order2).unwrap();
+    assert_eq!(exchange.place_order(order3), Err(MarketError::LimitExceeded("Open quantity in CORN would be over the limit of 50".to_string())));
+
+    // Another item has its own quantity, but every item counts toward the open orders
+    let order4 = OrderRequest::new("ALICE".to_string(), "WHEAT".to_string(), OrderKind::SELL, 40, Price::from(20));
+    let order5 = OrderRequest::new("ALICE".to_string(), "WHEAT".to_string(), OrderKind::SELL, 1, Price::from(20));
+    let order4 = exchange.place_order(order4).unwrap().created.unwrap();
+    assert_eq!(exchange.place_order(order5), Err(MarketError::LimitExceeded("Too many open orders; the limit is 2".to_string())));
+
+    // Orders that cannot rest take no slot
+    let mut order6 = OrderRequest::new("ALICE".to_string(), "WHEAT".to_string(), OrderKind::SELL, 1, Price::from(20));
+    order6.order.time_in_force = TimeInForce::ImmediateOrCancel;
+    exchange.place_order(order6).unwrap();
+
+    // Amending counts the new size in place of the old
+    assert!(exchange.amend_order(order4.id, 50, Price::from(20)).is_ok());
+    assert_eq!(exchange.amend_order(order4.id, 51, Price::from(19)), Err(MarketError::LimitExceeded("Open quantity in WHEAT would be over the limit of 50".to_string())));
+
+    // Other users are not affected
+    let order7 = OrderRequest::new("BOB".to_string(), "WHEAT".to_string(), OrderKind::BUY, 50, Price::from(5));
+    exchange.place_order(order7).unwrap();
+
+}
+
+#[test]
+fn test_risk_limits_free_up() {
+
+    let mut exchange = Market::new();
+    exchange.set_risk_limits(RiskLimits { max_open_orders: Some(2), max_open_quantity: Some(10), ..RiskLimits::default() }).unwrap();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 10, Price::from(5));
+    let order1 = exchange.place_order(order1).unwrap().created.unwrap();
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 4, Price::from(4));
+    assert_eq!(exchange.place_order(order2), Err(MarketError::LimitExceeded("Open quantity in CORN would be over the limit of 10".to_string())));
+
+    // What trades is no longer open
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::SELL, 4, Price::from(5));
+    let order4 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 4, Price::from(4));
+    let order5 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(3));
+    exchange.place_order(order3).unwrap();
+    let order4 = exchange.place_order(order4).unwrap().created.unwrap();
+    assert_eq!(exchange.place_order(order5), Err(MarketError::LimitExceeded("Too many open orders; the limit is 2".to_string())));
+
+    // Nor is what is cut or cancelled
+    exchange.amend_order(order1.id, 2, Price::from(5)).unwrap();
+    exchange.cancel(order4.id).unwrap();
+
+    let order6 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 8, Price::from(3));
+    let order7 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::BUY, 1, Price::from(3));
+    exchange.place_order(order6).unwrap();
+    assert_eq!(exchange.place_order(order7), Err(MarketError::LimitExceeded("Too many open orders; the limit is 2".to_string())));
+
+}
+
+#[test]
+fn test_risk_limits_on_market_orders() {
+
+    let mut exchange = Market::new();
+    exchange.set_risk_limits(RiskLimits { max_notional: Some(Price::from(100)), ..RiskLimits::default() }).unwrap();
+
+    let order1 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(10));
+    let order2 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::SELL, 5, Price::from(15));
+    exchange.place_order(order1).unwrap();
+    exchange.place_order(order2).unwrap();
+
+    // Not even the best offer fits
+    let order3 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 11, Price::from(0));
+    assert_eq!(exchange.place_order(order3), Err(MarketError::LimitExceeded("Order is worth more than the limit of 100".to_string())));
+
+    // Buys what the limit covers
+    let order4 = OrderRequest::new("BOB".to_string(), "CORN".to_string(), OrderKind::MARKET_BUY, 10, Price::from(0));
+    let summary = exchange.place_order(order4).unwrap();
+    assert_eq!(summary.filled(), 8);
+    assert_eq!(summary.transactions.iter().map(|x| x.value()).fold(Price::from(0), |a, b| a.checked_add(b).unwrap()), Price::from(95));
+
+    // The budget it spent was the limit's, not BOB's
+    assert_eq!((summary.spent, summary.budget_left), (None, None));
+
+    // A market sell is worth no more than the best bid
+    let order5 = OrderRequest::new("CAROL".to_string(), "CORN".to_string(), OrderKind::BUY, 5, Price::from(20));
+    let order6 = OrderRequest::new("ALICE".to_string(), "CORN".to_string(), OrderKind::MARKET_SELL, 6, Price::from(0));
+    exchange.place_order(order5).unwrap();
+    assert!(exchange.place_order(order6).is_err());
+
+    let bad_limits = RiskLimits { max_notional: Some(Price::from(0)), ..RiskLimits::default() };
+    assert_eq!(exchange.set_risk_limits(bad_limits), Err(MarketError::InvalidSpec("Notional limit must be positive".to_string())));
+
+}
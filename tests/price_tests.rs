@@ -0,0 +1,97 @@
+use MarketCore::price::Price;
+
+#[test]
+fn test_parse_and_display() {
+
+    let price: Price = "12.50".parse().unwrap();
+    assert_eq!(price, Price::new(1250, 2));
+    assert_eq!(price.to_string(), "12.50");
+
+    assert_eq!("0.1".parse::<Price>().unwrap().to_string(), "0.1");
+    assert_eq!("-0.05".parse::<Price>().unwrap().to_string(), "-0.05");
+    assert_eq!("7".parse::<Price>().unwrap(), Price::from(7));
+
+    assert!("".parse::<Price>().is_err());
+    assert!(".5".parse::<Price>().is_err());
+    assert!("1.2.3".parse::<Price>().is_err());
+    assert!("12,50".parse::<Price>().is_err());
+    assert!("1e5".parse::<Price>().is_err());
+
+}
+
+#[test]
+fn test_compare_across_scales() {
+
+    assert_eq!(Price::new(125, 1), Price::new(12500, 3));
+    assert!(Price::new(1, 1) < Price::new(11, 2));
+    assert!(Price::new(-1, 0) < Price::new(0, 5));
+
+    // 0.1 + 0.2 style drift cannot happen: the values are exact
+    let tenth: Price = "0.1".parse().unwrap();
+    assert_eq!(tenth, Price::new(10, 2));
+
+}
+
+#[test]
+fn test_rounding() {
+
+    let price: Price = "12.345".parse().unwrap();
+
+    assert_eq!(price.floor(2).unwrap().to_string(), "12.34");
+    assert_eq!(price.ceil(2).unwrap().to_string(), "12.35");
+    assert_eq!(price.rescale(2), None);
+    assert_eq!(price.rescale(4).unwrap().to_string(), "12.3450");
+
+    let negative: Price = "-1.5".parse().unwrap();
+    assert_eq!(negative.floor(0).unwrap(), Price::from(-2));
+    assert_eq!(negative.ceil(0).unwrap(), Price::from(-1));
+
+}
+
+#[test]
+fn test_serde() {
+
+    let price: Price = serde_json::from_str("\"0.30\"").unwrap();
+    assert_eq!(price.to_string(), "0.30");
+    assert_eq!(serde_json::to_string(&price).unwrap(), "\"0.30\"");
+
+    // Plain JSON numbers are still accepted, without going through binary floats
+    let price: Price = serde_json::from_str("0.3").unwrap();
+    assert_eq!(price.to_string(), "0.3");
+    let price: Price = serde_json::from_str("6").unwrap();
+    assert_eq!(price, Price::from(6));
+
+    assert!(serde_json::from_str::<Price>("\"abc\"").is_err());
+
+}
+
+#[test]
+fn test_arithmetic() {
+
+    let price: Price = "12.5".parse().unwrap();
+    let tick: Price = "0.05".parse().unwrap();
+
+    assert_eq!(price.checked_add(tick).unwrap().to_string(), "12.55");
+    assert_eq!(price.checked_sub(tick).unwrap().to_string(), "12.45");
+    assert_eq!(tick.checked_sub(price).unwrap().to_string(), "-12.45");
+    assert_eq!(Price::from(i64::MAX).checked_add(Price::from(1)), None);
+
+    assert_eq!(price.percent(Price::from(10)).unwrap().to_string(), "1.2");
+    assert_eq!(Price::new(1999, 2).percent("2.5".parse().unwrap()).unwrap().to_string(), "0.49");
+
+}
+
+#[test]
+fn test_multiply_and_fit() {
+
+    let price: Price = "2.50".parse().unwrap();
+    assert_eq!(price.checked_mul(4).unwrap().to_string(), "10.00");
+    assert!(Price::new(i64::MAX, 0).checked_mul(2).is_none());
+
+    let budget: Price = "10".parse().unwrap();
+    assert_eq!(budget.fits(price), 4);
+    assert_eq!(budget.fits("3.3".parse().unwrap()), 3);
+    assert_eq!(budget.fits(Price::from(0)), 0);
+    assert_eq!(Price::from(-10).fits(price), 0);
+
+}
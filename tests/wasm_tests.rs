@@ -19,7 +19,7 @@ fn test_buy() {
 
     println!("{}", summary);
 
-    let test_str = "{\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[],\"created\":{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0}}";
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[],\"created\":{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}}";
 
     assert!(WildMatch::new(test_str).matches(summary.as_str()));
 }
@@ -35,11 +35,67 @@ fn test_sell() {
 
     println!("{}", summary);
 
-    let test_str = "{\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[],\"created\":{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":6.0}}";
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[],\"created\":{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}}";
 
     assert!(WildMatch::new(test_str).matches(summary.as_str()));
 }
 
+#[test]
+fn test_decimal_price_strings() {
+    let sell_request_str =
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":\"0.1\"}";
+    let buy_request_str =
+        "{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":0.1}";
+
+    let mut exchange = MarketWrapper::new();
+
+    exchange.sell(&sell_request_str);
+    let summary = exchange.buy(&buy_request_str);
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"XANDER\",\"seller\":\"YOLANDE\",\"amount\":10,\"price_per\":\"0.10\"}],*";
+
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+}
+
+#[test]
+fn test_register_item() {
+    let mut exchange = MarketWrapper::new();
+
+    let status = exchange.register_item("NITROGEN".to_string(), "{\"tick_size\":\"0.25\",\"lot_size\":5}");
+    assert_eq!(status, "{\"status\":\"SUCCESS\"}");
+
+    let status = exchange.register_item("NITROGEN".to_string(), "{\"lot_size\":\"five\"}");
+    assert_eq!(status, "{\"status\":\"FAILURE\",\"reason\":\"Invalid item spec\"}");
+
+    let order_request_str =
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":345,\"price_per\":\"6.10\"}";
+    let summary = exchange.buy(&order_request_str);
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Price must be a multiple of the tick size 0.25\"}";
+
+    assert_eq!(test_str, summary);
+}
+
+#[test]
+fn test_halt() {
+    let order_request_str =
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":347,\"price_per\":6}";
+
+    let mut exchange = MarketWrapper::new();
+
+    assert_eq!(exchange.halt("NITROGEN".to_string()), "{\"status\":\"SUCCESS\"}");
+    let summary = exchange.buy(&order_request_str);
+    assert_eq!(summary, "{\"status\":\"FAILURE\",\"reason\":\"Trading in NITROGEN is halted\"}");
+
+    exchange.resume("NITROGEN".to_string());
+    let summary = exchange.buy(&order_request_str);
+    assert!(summary.starts_with("{\"status\":\"SUCCESS\""));
+}
+
 #[test]
 fn test_buy_and_sell() {
     let order_request_str =
@@ -52,7 +108,7 @@ fn test_buy_and_sell() {
 
     println!("{}", summary);
 
-    let test_str = "{\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"YOLANDE\",\"amount\":347,\"price_per\":6.0}],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":0,\"price_per\":6.0}],\"created\":null}";
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"YOLANDE\",\"amount\":347,\"price_per\":\"6.00\"}],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":0,\"price_per\":\"6.00\",\"seq\":*}],\"created\":null}";
 
     assert!(WildMatch::new(test_str).matches(summary.as_str()));
 }
@@ -70,7 +126,7 @@ fn test_buy_buy_sell() {
 
     println!("{}", summary);
 
-    let test_str = "{\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"YOLANDE\",\"amount\":347,\"price_per\":6.0}],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":0,\"price_per\":6.0}],\"created\":null}";
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"YOLANDE\",\"amount\":347,\"price_per\":\"6.00\"}],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":0,\"price_per\":\"6.00\",\"seq\":*}],\"created\":null}";
 
     assert!(WildMatch::new(test_str).matches(summary.as_str()));
 }
@@ -93,7 +149,7 @@ fn test_query_ledger() {
 
     println!("{}", response);
 
-    let test_str = "{\"buy_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0}],\"sell_orders\":[]}";
+    let test_str = "{\"buy_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}],\"sell_orders\":[],\"last_price\":\"6.00\"}";
 
     assert!(WildMatch::new(test_str).matches(response.as_str()));
 }
@@ -140,7 +196,7 @@ fn test_cancel_order_fail_no_key() {
 
     println!("{}", cancellation_status);
 
-    let test_str = "{ \"status\": \"FAILURE\", \"reason\" : \"Order does not exist\" }";
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Order does not exist\"}";
 
     assert!(WildMatch::new(test_str).matches(cancellation_status.as_str()));
 }
@@ -164,7 +220,42 @@ fn test_cancel_order_fail_bad_uuid() {
 
     println!("{}", cancellation_status);
 
-    let test_str = "{ \"status\": \"FAILURE\", \"reason\" : \"Invalid UUID string\" }";
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Invalid UUID string\"}";
+
+    assert!(WildMatch::new(test_str).matches(cancellation_status.as_str()));
+}
+
+#[test]
+fn test_cancel_by_id() {
+    let order_request_str =
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":347,\"price_per\":6}";
+
+    let mut exchange = MarketWrapper::new();
+
+    let summary = exchange.buy(&order_request_str);
+    let summary: serde_json::Value = serde_json::from_str(&summary).unwrap();
+    let id = summary["created"]["id"].as_str().unwrap().to_string();
+
+    let cancellation_status = exchange.cancel_by_id("NITROGEN".to_string(), id.clone());
+
+    println!("{}", cancellation_status);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"order\":{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":0}}";
+
+    assert!(WildMatch::new(test_str).matches(cancellation_status.as_str()));
+
+    let cancellation_status = exchange.cancel(id);
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Order does not exist\"}";
+
+    assert!(WildMatch::new(test_str).matches(cancellation_status.as_str()));
+}
+
+#[test]
+fn test_cancel_fail_bad_uuid() {
+    let mut exchange = MarketWrapper::new();
+
+    let cancellation_status = exchange.cancel("bad_uuid_string".to_string());
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Invalid UUID string\"}";
 
     assert!(WildMatch::new(test_str).matches(cancellation_status.as_str()));
 }
@@ -184,7 +275,7 @@ fn test_get_best_buy_price() {
     println!("{}", exchange.query_ledger("NITROGEN".to_string()));
 
     let test_str =
-        "{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":8.0}";
+        "{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"8.00\",\"seq\":*}";
 
     println!("{}", test_str);
 
@@ -210,7 +301,7 @@ fn test_get_best_sell_price() {
     println!("{}", exchange.query_ledger("NITROGEN".to_string()));
 
     let test_str =
-        "{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":6.0}";
+        "{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}";
 
     println!("{}", test_str);
 
@@ -232,7 +323,7 @@ fn test_dump_market() {
     exchange.buy(&buy_request_str);
 
     let response = exchange.dump();
-    let test_str: &str = "{\"NITROGEN\":{\"buy_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0},{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0}],\"sell_orders\":[]}}";
+    let test_str: &str = "{\"NITROGEN\":{\"buy_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*},{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}],\"sell_orders\":[]}}";
     assert!(WildMatch::new(test_str).matches(response.as_str()));
 
     // Test Sell
@@ -242,22 +333,312 @@ fn test_dump_market() {
     exchange.sell(&sell_request_str);
 
     let response = exchange.dump();
-    let test_str: &str = "{\"WEED\":{\"buy_orders\":[],\"sell_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":6.0}]}}";
+    let test_str: &str = "{\"WEED\":{\"buy_orders\":[],\"sell_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}]}}";
     assert!(WildMatch::new(test_str).matches(response.as_str()));
 }
 
 #[test]
 fn test_load_market() {
-    let market_string: &str = "{\"NITROGEN\":{\"buy_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0},{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0}],\"sell_orders\":[]},\"WEED\":{\"buy_orders\":[],\"sell_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":6.0}]}}";
-    let mut exchange = MarketWrapper::load(market_string.to_string());
+    let market_string: &str = "{\"NITROGEN\":{\"buy_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\"},{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\"}],\"sell_orders\":[]},\"WEED\":{\"buy_orders\":[],\"sell_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":\"6.00\"}]}}";
+    let mut exchange = MarketWrapper::load(market_string.to_string()).unwrap();
 
     let query_str = "NITROGEN".to_string();
     let response = exchange.query_ledger(query_str);
-    let test_str = "{\"buy_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":6.0}],\"sell_orders\":[]}";
+    let test_str = "{\"buy_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}],\"sell_orders\":[]}";
     assert!(WildMatch::new(test_str).matches(response.as_str()));
 
     let query_str = "WEED".to_string();
     let response = exchange.query_ledger(query_str);
-    let test_str = "{\"buy_orders\":[],\"sell_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":6.0}]}";
+    let test_str = "{\"buy_orders\":[],\"sell_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":*}]}";
     assert!(WildMatch::new(test_str).matches(response.as_str()));
 }
+
+#[test]
+fn test_malformed_input() {
+    let mut exchange = MarketWrapper::new();
+
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Invalid order request\"}";
+    assert_eq!(exchange.buy("not json"), test_str);
+    assert_eq!(exchange.sell("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":-1,\"price_per\":6}"), test_str);
+    assert_eq!(exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":1,\"price_per\":\"1e99\"}"), test_str);
+
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Invalid order\"}";
+    assert_eq!(exchange.cancel_order("NITROGEN".to_string(), "{\"id\":5}".to_string()), test_str);
+
+    let test_str = "{\"status\":\"FAILURE\",\"reason\":\"Invalid market data\"}";
+    assert_eq!(MarketWrapper::load("[1, 2".to_string()).err().unwrap(), test_str);
+
+    // Extreme but well-formed values must not overflow
+    let market_string = "{\"NITROGEN\":{\"buy_orders\":[{\"id\":\"38e7b46b-ae36-43f9-aa14-cf776625b58c\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":347,\"price_per\":\"6.00\",\"seq\":18446744073709551615}],\"sell_orders\":[]}}";
+    let mut exchange = MarketWrapper::load(market_string.to_string()).unwrap();
+    let summary = exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":1,\"price_per\":\"9223372036854775807\"}");
+    assert!(summary.starts_with("{\"status\":\"SUCCESS\""));
+}
+
+#[test]
+fn test_market_buy_and_sell() {
+    let sell_request_str =
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":\"6.50\"}";
+    let market_request_str =
+        "{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":4}";
+
+    let mut exchange = MarketWrapper::new();
+
+    exchange.sell(&sell_request_str);
+    let summary = exchange.market_buy(&market_request_str);
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"XANDER\",\"seller\":\"YOLANDE\",\"amount\":4,\"price_per\":\"6.50\"}],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":6,\"price_per\":\"6.50\",\"seq\":0}],\"created\":null}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+
+    exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":3,\"price_per\":\"6.00\"}");
+    let summary = exchange.market_sell(&market_request_str);
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"XANDER\",\"amount\":3,\"price_per\":\"6.00\"}],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":0,\"price_per\":\"6.00\",\"seq\":*}],\"created\":null,\"cancelled\":[{*\"amount\":1,*\"reason\":\"NO_LIQUIDITY\"}]}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+}
+
+#[test]
+fn test_place_order_kind() {
+    let mut exchange = MarketWrapper::new();
+
+    let summary = exchange.place_order("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"kind\":\"SELL\",\"amount\":5,\"price_per\":6}");
+    assert!(summary.contains("\"kind\":\"SELL\""));
+
+    let summary = exchange.place_order("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":5}");
+    assert!(summary.contains("\"kind\":\"BUY\""));
+
+    let summary = exchange.place_order("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"kind\":\"SIDEWAYS\",\"amount\":5,\"price_per\":5}");
+    assert_eq!(summary, "{\"status\":\"FAILURE\",\"reason\":\"Invalid order request\"}");
+}
+
+#[test]
+fn test_market_buy_no_liquidity() {
+    let mut exchange = MarketWrapper::new();
+
+    let summary = exchange.market_buy("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":4}");
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[],\"created\":null,\"cancelled\":[{\"order\":{\"id\":\"*\",\"user_id\":\"XANDER\",\"kind\":\"MARKET_BUY\",\"amount\":4,\"price_per\":\"0.00\",\"seq\":0},\"reason\":\"NO_LIQUIDITY\"}]}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+    assert_eq!(exchange.query_ledger("NITROGEN".to_string()), "{}");
+}
+
+#[test]
+fn test_time_in_force() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.sell("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":6}");
+    let summary = exchange.buy("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":8,\"price_per\":6,\"time_in_force\":\"FOK\"}");
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[],\"created\":null,\"cancelled\":[{\"order\":{\"id\":\"*\",\"user_id\":\"XANDER\",\"kind\":\"BUY\",\"amount\":8,\"price_per\":\"6.00\",\"seq\":1,\"time_in_force\":\"FOK\"},\"reason\":\"FILL_OR_KILL\"}]}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+
+    let summary = exchange.buy("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":8,\"price_per\":6,\"time_in_force\":\"IOC\"}");
+    assert!(summary.contains("\"amount\":3,\"price_per\":\"6.00\",\"seq\":2,\"time_in_force\":\"IOC\"},\"reason\":\"IMMEDIATE_OR_CANCEL\""));
+}
+
+#[test]
+fn test_expire_orders() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.set_default_lifetime(Some(10.0));
+    exchange.sell("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":6}");
+    exchange.sell("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":7,\"expires_at\":30}");
+
+    assert_eq!(exchange.expire_orders(9.0), "{}");
+
+    // Times come in as JavaScript numbers, and must be whole
+    let invalid = "{\"status\":\"FAILURE\",\"reason\":\"Invalid time\"}";
+    assert_eq!(exchange.set_time(9.5), invalid);
+    assert_eq!(exchange.set_time(-1.0), invalid);
+    assert_eq!(exchange.set_time(f64::NAN), invalid);
+    assert_eq!(exchange.set_default_lifetime(Some(1e300)), invalid);
+    assert_eq!(exchange.expire_orders(f64::INFINITY), invalid);
+    assert_eq!(exchange.set_time(9.0), "{\"status\":\"SUCCESS\"}");
+
+    let expired = exchange.expire_orders(10.0);
+
+    println!("{}", expired);
+
+    let test_str = "{\"NITROGEN\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"SELL\",\"amount\":5,\"price_per\":\"6.00\",\"seq\":0,\"expires_at\":10}]}";
+    assert!(WildMatch::new(test_str).matches(expired.as_str()));
+}
+
+#[test]
+fn test_post_only() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.sell("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":6}");
+    let summary = exchange.buy("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":6,\"post_only\":\"REJECT\"}");
+    assert!(summary.ends_with("\"post_only\":\"REJECT\"},\"reason\":\"POST_ONLY\"}]}"));
+
+    let summary = exchange.buy("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":5,\"price_per\":6,\"post_only\":\"REPRICE\"}");
+    assert!(summary.contains("\"created\":{\"id\":"));
+    assert!(summary.contains("\"price_per\":\"5.99\""));
+}
+
+#[test]
+fn test_stop_order() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":5}");
+    let summary = exchange.place_order("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"kind\":\"STOP_SELL\",\"amount\":4,\"trigger_price\":\"5.50\"}");
+    assert!(summary.contains("\"kind\":\"STOP_SELL\",\"amount\":4,\"price_per\":\"0.00\",\"seq\":1,\"trigger_price\":\"5.50\"}"));
+
+    let summary = exchange.sell("{\"user_id\":\"WENDY\",\"item\":\"NITROGEN\",\"amount\":1,\"price_per\":5}");
+
+    println!("{}", summary);
+
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"WENDY\",\"amount\":1,\"price_per\":\"5.00\"},{\"buyer\":\"YOLANDE\",\"seller\":\"XANDER\",\"amount\":4,\"price_per\":\"5.00\"}],*\"created\":null,\"triggered\":[{*\"user_id\":\"XANDER\",\"kind\":\"STOP_SELL\",*}]}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+}
+
+#[test]
+fn test_iceberg() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":300,\"price_per\":6,\"display\":100}");
+
+    let test_str = "{\"buy_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":100,\"price_per\":\"6.00\",\"seq\":0}],\"sell_orders\":[]}";
+    assert!(WildMatch::new(test_str).matches(exchange.query_ledger("NITROGEN".to_string()).as_str()));
+
+    let test_str = "{\"NITROGEN\":{\"buy_orders\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":100,\"price_per\":\"6.00\",\"seq\":0,\"display\":100,\"reserve\":200}],\"sell_orders\":[]}}";
+    assert!(WildMatch::new(test_str).matches(exchange.dump().as_str()));
+
+    let summary = exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":150,\"price_per\":6}");
+    assert!(summary.ends_with("\"created\":null,\"hidden_filled\":50}"));
+}
+
+#[test]
+fn test_place_oco() {
+    let mut exchange = MarketWrapper::new();
+
+    let summary = exchange.place_oco(
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"kind\":\"SELL\",\"amount\":10,\"price_per\":120}",
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"kind\":\"STOP_SELL\",\"amount\":10,\"trigger_price\":80}",
+    );
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[{*\"kind\":\"STOP_SELL\"*\"oco\":\"*\"}],\"created\":{*\"kind\":\"SELL\"*\"oco\":\"*\"},\"linked\":[{*\"kind\":\"STOP_SELL\"*}]}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+
+    // The public view keeps the pair private
+    assert!(!exchange.query_ledger("NITROGEN".to_string()).contains("oco"));
+
+    let summary = exchange.buy("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":120}");
+    assert!(WildMatch::new("*\"cancelled\":[{\"order\":{*\"kind\":\"STOP_SELL\"*},\"reason\":\"LINKED\"}]*").matches(summary.as_str()));
+
+    // Cancelling one leg reports the other going with it
+    let summary = exchange.place_oco(
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"kind\":\"SELL\",\"amount\":10,\"price_per\":120}",
+        "{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"kind\":\"STOP_SELL\",\"amount\":10,\"trigger_price\":80}",
+    );
+    let id = summary.split("\"created\":{\"id\":\"").nth(1).unwrap().split('"').next().unwrap().to_string();
+    let status = exchange.cancel(id);
+    assert!(WildMatch::new("{\"status\":\"SUCCESS\",\"order\":{*\"kind\":\"SELL\"*},\"linked\":{*\"kind\":\"STOP_SELL\"*}}").matches(status.as_str()));
+
+    let summary = exchange.place_oco("{}", "{}");
+    assert_eq!(summary, "{\"status\":\"FAILURE\",\"reason\":\"Invalid order request\"}");
+}
+
+#[test]
+fn test_amend_order() {
+    let mut exchange = MarketWrapper::new();
+
+    let summary = exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    let id = summary.split("\"id\":\"").nth(1).unwrap().split('"').next().unwrap().to_string();
+
+    let summary = exchange.amend_order(id.clone(), 5, "6");
+    let test_str = "{\"status\":\"SUCCESS\",\"key\":\"NITROGEN\",\"transactions\":[],\"to_update\":[{\"id\":\"*\",\"user_id\":\"YOLANDE\",\"kind\":\"BUY\",\"amount\":5,\"price_per\":\"6.00\",\"seq\":0}],\"created\":null}";
+    assert!(WildMatch::new(test_str).matches(summary.as_str()));
+
+    assert_eq!(exchange.amend_order(id.clone(), 5, "six"), "{\"status\":\"FAILURE\",\"reason\":\"Invalid price\"}");
+    assert_eq!(exchange.amend_order("nope".to_string(), 5, "6"), "{\"status\":\"FAILURE\",\"reason\":\"Invalid UUID string\"}");
+}
+
+#[test]
+fn test_market_buy_slippage() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":60}");
+
+    let summary = exchange.market_buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":20,\"slippage\":{\"OFFSET\":\"1.00\"}}");
+    assert!(WildMatch::new("*\"amount\":10,\"price_per\":\"6.00\"}]*\"cancelled\":[{\"order\":{*\"amount\":10,*\"slippage\":{\"OFFSET\":\"1.00\"}},\"reason\":\"SLIPPAGE\"}]}").matches(summary.as_str()));
+}
+
+#[test]
+fn test_market_buy_by_budget() {
+    let mut exchange = MarketWrapper::new();
+
+    exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":\"2.50\"}");
+
+    let summary = exchange.market_buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"budget\":\"11\"}");
+    assert!(WildMatch::new("*\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"XANDER\",\"amount\":4,\"price_per\":\"2.50\"}]*\"spent\":\"10.00\",\"budget_left\":\"1.00\"}").matches(summary.as_str()));
+}
+
+#[test]
+fn test_self_trade_prevention() {
+    let mut exchange = MarketWrapper::new();
+
+    assert_eq!(exchange.set_self_trade_prevention(Some("SOMETIMES".to_string())), "{\"status\":\"FAILURE\",\"reason\":\"Invalid self-trade prevention mode\"}");
+    assert_eq!(exchange.set_self_trade_prevention(Some("CANCEL_NEWEST".to_string())), "{\"status\":\"SUCCESS\"}");
+
+    exchange.sell("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    let summary = exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    assert!(WildMatch::new("*\"transactions\":[]*\"reason\":\"SELF_TRADE\"}]}").matches(summary.as_str()));
+
+    assert_eq!(exchange.set_self_trade_prevention(None), "{\"status\":\"SUCCESS\"}");
+    let summary = exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    assert!(summary.contains("\"buyer\":\"YOLANDE\",\"seller\":\"YOLANDE\""));
+}
+
+#[test]
+fn test_accounts() {
+    let mut exchange = MarketWrapper::new();
+
+    assert_eq!(exchange.deposit("YOLANDE".to_string(), "50"), "{\"status\":\"FAILURE\",\"reason\":\"Accounts are not enabled\"}");
+    assert_eq!(exchange.enable_accounts(), "{\"status\":\"SUCCESS\"}");
+    assert_eq!(exchange.deposit("YOLANDE".to_string(), "50"), "{\"status\":\"SUCCESS\"}");
+    assert_eq!(exchange.deposit_items("XANDER".to_string(), "NITROGEN".to_string(), 10), "{\"status\":\"SUCCESS\"}");
+
+    let summary = exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    assert_eq!(summary, "{\"status\":\"FAILURE\",\"reason\":\"Insufficient funds\"}");
+
+    exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":5}");
+    exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":4,\"price_per\":5}");
+
+    assert_eq!(exchange.query_account("XANDER".to_string()), "{\"balance\":\"20.00\",\"items\":{\"NITROGEN\":6},\"held\":\"0\",\"held_items\":{\"NITROGEN\":6}}");
+    assert_eq!(exchange.query_account("YOLANDE".to_string()), "{\"balance\":\"30.00\",\"items\":{\"NITROGEN\":4},\"held\":\"0\",\"held_items\":{}}");
+    assert_eq!(exchange.query_account("ZED".to_string()), "{}");
+    assert_eq!(exchange.load_accounts("[]".to_string()), "{\"status\":\"FAILURE\",\"reason\":\"Invalid account data\"}");
+}
+
+#[test]
+fn test_fees() {
+    let mut exchange = MarketWrapper::new();
+
+    assert_eq!(exchange.set_fees(Some("{\"taker_bps\":\"lots\"}".to_string())), "{\"status\":\"FAILURE\",\"reason\":\"Invalid fee schedule\"}");
+    assert_eq!(exchange.set_fees(Some("{\"maker_bps\":10,\"taker_bps\":20}".to_string())), "{\"status\":\"SUCCESS\"}");
+
+    exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    let summary = exchange.buy("{\"user_id\":\"YOLANDE\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    assert!(WildMatch::new("*\"transactions\":[{\"buyer\":\"YOLANDE\",\"seller\":\"XANDER\",\"amount\":10,\"price_per\":\"6.00\",\"buyer_fee\":\"0.12\",\"seller_fee\":\"0.06\"}]*\"fees\":\"0.18\"}").matches(summary.as_str()));
+}
+
+#[test]
+fn test_risk_limits() {
+    let mut exchange = MarketWrapper::new();
+
+    assert_eq!(exchange.set_risk_limits("{\"max_open_orders\":-1}"), "{\"status\":\"FAILURE\",\"reason\":\"Invalid risk limits\"}");
+    assert_eq!(exchange.set_risk_limits("{\"max_open_orders\":1}"), "{\"status\":\"SUCCESS\"}");
+
+    exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":6}");
+    let summary = exchange.sell("{\"user_id\":\"XANDER\",\"item\":\"NITROGEN\",\"amount\":10,\"price_per\":7}");
+    assert_eq!(summary, "{\"status\":\"FAILURE\",\"reason\":\"Too many open orders; the limit is 1\"}");
+}